@@ -0,0 +1,198 @@
+//! Code collision (重码) statistics of a [`Table`].
+
+use crate::table::{Candidate, Table, WubiCode};
+use itertools::Itertools as _;
+use std::{cmp::Reverse, collections::BTreeSet, fmt};
+
+/// Collisions among the codes of one length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LengthStats {
+    /// Number of letters of the codes.
+    pub length: usize,
+    /// Codes with at least one candidate.
+    pub codes: usize,
+    /// Codes with more than one candidate.
+    pub ambiguous: usize,
+    /// Candidates of all codes.
+    pub candidates: usize,
+    /// Codes with the most candidates, most first.
+    pub worst: Vec<(WubiCode, Vec<String>)>,
+}
+
+/// Collision report of a table, as built by [`CollisionReport::new`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollisionReport {
+    /// Statistics per code length, shortest first.
+    pub lengths: Vec<LengthStats>,
+    /// Common characters examined.
+    pub common: usize,
+    /// Common characters that are not the first candidate of any of their
+    /// codes, and so always need a selection key.
+    pub needs_selection: Vec<char>,
+    /// Phrases of two or more characters.
+    pub phrases: usize,
+    /// Phrases sharing a code with a single character.
+    pub phrases_colliding: usize,
+}
+
+impl CollisionReport {
+    /// Analyses `table`, listing the `top` worst codes of each length and
+    /// checking the characters of `common`.
+    pub fn new(table: &Table, common: &[char], top: usize) -> Self {
+        let mut lengths: Vec<_> = (1..=WubiCode::MAX_KEYS)
+            .map(|length| LengthStats {
+                length,
+                codes: 0,
+                ambiguous: 0,
+                candidates: 0,
+                worst: Vec::new(),
+            })
+            .collect();
+        let mut colliding = BTreeSet::new();
+        let mut by_length = vec![Vec::new(); WubiCode::MAX_KEYS];
+        for (code, candidates) in table.candidates() {
+            let stats = &mut lengths[code.key_count() - 1];
+            stats.codes += 1;
+            stats.candidates += candidates.len();
+            if candidates.len() < 2 {
+                continue;
+            }
+            stats.ambiguous += 1;
+            by_length[code.key_count() - 1].push((code, candidates.len()));
+
+            let is_char =
+                |candidate: &Candidate<'_>| candidate.to_string().chars().nth(1).is_none();
+            if candidates.iter().any(is_char) {
+                colliding.extend(
+                    candidates
+                        .iter()
+                        .filter(|candidate| !is_char(candidate))
+                        .map(ToString::to_string),
+                );
+            }
+        }
+        for (stats, codes) in lengths.iter_mut().zip(by_length) {
+            stats.worst = codes
+                .into_iter()
+                .sorted_by_key(|&(code, count)| (Reverse(count), code))
+                .take(top)
+                .map(|(code, _)| {
+                    let candidates = table
+                        .lookup(&code)
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect();
+                    (code, candidates)
+                })
+                .collect();
+        }
+
+        let needs_selection = common
+            .iter()
+            .copied()
+            .filter(|&ch| {
+                let codes = table.codes_of(&ch.to_string());
+                !codes.is_empty() && codes.iter().all(|code| code.rank != Some(0))
+            })
+            .collect();
+        let phrases = table
+            .full()
+            .entries()
+            .filter(|(phrase, _)| phrase.chars().nth(1).is_some())
+            .count();
+        Self {
+            lengths,
+            common: common.len(),
+            needs_selection,
+            phrases,
+            phrases_colliding: colliding.len(),
+        }
+    }
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+impl fmt::Display for CollisionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stats in &self.lengths {
+            writeln!(
+                f,
+                "{}-letter codes: {} used, {} ambiguous ({:.1}%), {} candidates",
+                stats.length,
+                stats.codes,
+                stats.ambiguous,
+                percent(stats.ambiguous, stats.codes),
+                stats.candidates
+            )?;
+            for (code, candidates) in &stats.worst {
+                writeln!(
+                    f,
+                    "  {code} ({}) {}",
+                    candidates.len(),
+                    candidates.join(" ")
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "common characters needing a selection key: {} of {} ({:.1}%)",
+            self.needs_selection.len(),
+            self.common,
+            percent(self.needs_selection.len(), self.common)
+        )?;
+        if !self.needs_selection.is_empty() {
+            writeln!(f, "  {}", self.needs_selection.iter().join(""))?;
+        }
+        write!(
+            f,
+            "phrases sharing a code with a character: {} of {} ({:.1}%)",
+            self.phrases_colliding,
+            self.phrases,
+            percent(self.phrases_colliding, self.phrases)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::{code, table};
+
+    #[test]
+    fn collisions() {
+        let table = table(
+            &[("k", '中')],
+            &[
+                ("中", "khk"),
+                ("国", "lgyi"),
+                ("回", "lkd"),
+                ("吧", "kcn"),
+                ("中国", "khlg"),
+                ("跨国", "khlg"),
+                ("因", "ldi"),
+                ("囗", "lkd"),
+                ("中华", "khk"),
+            ],
+        );
+        let report = CollisionReport::new(&table, &['中', '囗', '国'], 1);
+
+        let counts: Vec<_> = report
+            .lengths
+            .iter()
+            .map(|stats| (stats.codes, stats.ambiguous, stats.candidates))
+            .collect();
+        assert_eq!(counts, [(1, 0, 1), (0, 0, 0), (4, 2, 6), (2, 1, 3)]);
+        assert_eq!(
+            report.lengths[2].worst,
+            [(code("khk"), vec!["中".to_string(), "中华".to_string()])]
+        );
+        assert_eq!(report.needs_selection, ['囗']);
+        assert_eq!((report.phrases, report.phrases_colliding), (3, 1));
+    }
+}
@@ -0,0 +1,221 @@
+//! Unicode blocks of CJK characters and filters over them.
+
+use crate::ParseError;
+use itertools::Itertools as _;
+use std::{collections::BTreeSet, fmt, ops::RangeInclusive, str::FromStr};
+
+/// A Unicode block a character of the table can belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Block {
+    /// CJK Unified Ideographs, U+4E00..U+9FFF.
+    Basic,
+    ExtA,
+    ExtB,
+    ExtC,
+    ExtD,
+    ExtE,
+    ExtF,
+    ExtG,
+    ExtH,
+    ExtI,
+    /// CJK Compatibility Ideographs.
+    Compat,
+    /// CJK Compatibility Ideographs Supplement.
+    CompatSupplement,
+    /// CJK Radicals Supplement and Kangxi Radicals.
+    Radicals,
+    /// CJK Symbols and Punctuation, e.g. `〇`.
+    Symbols,
+    /// CJK Strokes.
+    Strokes,
+    /// Anything outside the blocks above.
+    Other,
+}
+
+impl Block {
+    pub const ALL: [Block; 16] = [
+        Block::Basic,
+        Block::ExtA,
+        Block::ExtB,
+        Block::ExtC,
+        Block::ExtD,
+        Block::ExtE,
+        Block::ExtF,
+        Block::ExtG,
+        Block::ExtH,
+        Block::ExtI,
+        Block::Compat,
+        Block::CompatSupplement,
+        Block::Radicals,
+        Block::Symbols,
+        Block::Strokes,
+        Block::Other,
+    ];
+
+    /// Codepoints of the block, or `None` for [`Block::Other`].
+    pub fn range(self) -> Option<RangeInclusive<char>> {
+        Some(match self {
+            Block::Basic => '\u{4e00}'..='\u{9fff}',
+            Block::ExtA => '\u{3400}'..='\u{4dbf}',
+            Block::ExtB => '\u{20000}'..='\u{2a6df}',
+            Block::ExtC => '\u{2a700}'..='\u{2b73f}',
+            Block::ExtD => '\u{2b740}'..='\u{2b81f}',
+            Block::ExtE => '\u{2b820}'..='\u{2ceaf}',
+            Block::ExtF => '\u{2ceb0}'..='\u{2ebef}',
+            Block::ExtG => '\u{30000}'..='\u{3134f}',
+            Block::ExtH => '\u{31350}'..='\u{323af}',
+            Block::ExtI => '\u{2ebf0}'..='\u{2ee5f}',
+            Block::Compat => '\u{f900}'..='\u{faff}',
+            Block::CompatSupplement => '\u{2f800}'..='\u{2fa1f}',
+            Block::Radicals => '\u{2e80}'..='\u{2fdf}',
+            Block::Symbols => '\u{3000}'..='\u{303f}',
+            Block::Strokes => '\u{31c0}'..='\u{31ef}',
+            Block::Other => return None,
+        })
+    }
+
+    /// The block `ch` belongs to.
+    pub fn of(ch: char) -> Block {
+        Block::ALL
+            .into_iter()
+            .find(|block| block.range().is_some_and(|range| range.contains(&ch)))
+            .unwrap_or(Block::Other)
+    }
+
+    /// Name used on the command line.
+    pub fn key(self) -> &'static str {
+        match self {
+            Block::Basic => "basic",
+            Block::ExtA => "ext-a",
+            Block::ExtB => "ext-b",
+            Block::ExtC => "ext-c",
+            Block::ExtD => "ext-d",
+            Block::ExtE => "ext-e",
+            Block::ExtF => "ext-f",
+            Block::ExtG => "ext-g",
+            Block::ExtH => "ext-h",
+            Block::ExtI => "ext-i",
+            Block::Compat => "compat",
+            Block::CompatSupplement => "compat-supplement",
+            Block::Radicals => "radicals",
+            Block::Symbols => "symbols",
+            Block::Strokes => "strokes",
+            Block::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Block::Basic => "CJK Unified Ideographs",
+            Block::ExtA => "CJK Extension A",
+            Block::ExtB => "CJK Extension B",
+            Block::ExtC => "CJK Extension C",
+            Block::ExtD => "CJK Extension D",
+            Block::ExtE => "CJK Extension E",
+            Block::ExtF => "CJK Extension F",
+            Block::ExtG => "CJK Extension G",
+            Block::ExtH => "CJK Extension H",
+            Block::ExtI => "CJK Extension I",
+            Block::Compat => "CJK Compatibility Ideographs",
+            Block::CompatSupplement => "CJK Compatibility Ideographs Supplement",
+            Block::Radicals => "CJK Radicals",
+            Block::Symbols => "CJK Symbols and Punctuation",
+            Block::Strokes => "CJK Strokes",
+            Block::Other => "Other",
+        })
+    }
+}
+
+impl FromStr for Block {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Block::ALL
+            .into_iter()
+            .find(|block| block.key() == s)
+            .ok_or_else(|| {
+                let keys = Block::ALL.into_iter().map(Block::key).join(", ");
+                ParseError::UnknownName(s.to_string(), keys)
+            })
+    }
+}
+
+/// The set of blocks whose characters are loaded into the table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockFilter {
+    blocks: BTreeSet<Block>,
+}
+
+impl BlockFilter {
+    /// Accepts every character.
+    pub fn all() -> Self {
+        Self {
+            blocks: Block::ALL.into_iter().collect(),
+        }
+    }
+
+    pub fn only(blocks: impl IntoIterator<Item = Block>) -> Self {
+        Self {
+            blocks: blocks.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, block: Block) -> bool {
+        self.blocks.contains(&block)
+    }
+
+    /// Whether every character of `phrase` is in an accepted block.
+    pub fn accepts(&self, phrase: &str) -> bool {
+        phrase.chars().all(|ch| self.contains(Block::of(ch)))
+    }
+}
+
+impl Default for BlockFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromStr for BlockFilter {
+    type Err = ParseError;
+
+    /// Parses `all` or a comma-separated list of [`Block::key`]s.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Self::all());
+        }
+        s.split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(|blocks| Self { blocks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_of_char() {
+        assert_eq!(Block::of('一'), Block::Basic);
+        assert_eq!(Block::of('㐀'), Block::ExtA);
+        assert_eq!(Block::of('𠀀'), Block::ExtB);
+        assert_eq!(Block::of('〇'), Block::Symbols);
+        assert_eq!(Block::of('a'), Block::Other);
+
+        let filter: BlockFilter = "basic,ext-a".parse().unwrap();
+        assert!(filter.accepts("一㐀"));
+        assert!(!filter.accepts("一𠀀"));
+        assert_eq!(
+            "basic,ext-z"
+                .parse::<BlockFilter>()
+                .unwrap_err()
+                .to_string(),
+            "Unknown name \"ext-z\", expected one of: basic, ext-a, ext-b, ext-c, ext-d, \
+             ext-e, ext-f, ext-g, ext-h, ext-i, compat, compat-supplement, radicals, symbols, \
+             strokes, other"
+        );
+    }
+}
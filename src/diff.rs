@@ -0,0 +1,376 @@
+//! Semantic differences between two builds of a table.
+//!
+//! Each side is a [`Snapshot`], taken from a [`Table`] or read back from a
+//! generated `wb_nc_table.txt` by [`load_snapshot`](crate::load::load_snapshot),
+//! and [`TableDiff::new`] compares two of them.
+
+#[cfg(feature = "json")]
+use crate::strings;
+use crate::table::{Table, WubiCode};
+use itertools::{EitherOrBoth, Itertools as _};
+#[cfg(feature = "json")]
+use serde_json::{Value, json};
+use std::{collections::BTreeMap, fmt};
+
+/// The records of a table that are compared: the candidates of each code, the
+/// simplified codes and, if known, the codes of each phrase.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    codes: BTreeMap<WubiCode, Vec<String>>,
+    /// `None` if not known, see [`Snapshot::from_records`].
+    simplified: Option<BTreeMap<WubiCode, char>>,
+    reverse: Option<BTreeMap<String, Vec<WubiCode>>>,
+}
+
+impl Snapshot {
+    pub fn new(table: &Table) -> Self {
+        Self {
+            codes: table
+                .candidates()
+                .map(|(code, candidates)| {
+                    (code, candidates.iter().map(ToString::to_string).collect())
+                })
+                .collect(),
+            simplified: Some(table.simplified_table().collect()),
+            reverse: Some(
+                table
+                    .reverse_entries()
+                    .map(|(phrase, codes)| (phrase.into_owned(), codes))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Builds a snapshot from generated records, which do not tell simplified
+    /// codes apart.
+    pub fn from_records(
+        codes: BTreeMap<WubiCode, Vec<String>>,
+        reverse: Option<BTreeMap<String, Vec<WubiCode>>>,
+    ) -> Self {
+        Self {
+            codes,
+            simplified: None,
+            reverse,
+        }
+    }
+
+    /// Simplified codes as guessed from the records: a code whose first
+    /// candidate is a single character with a longer code, as listed by the
+    /// reverse records if any, or else by the candidates.
+    fn guessed_simplified(&self) -> BTreeMap<WubiCode, char> {
+        let mut longest: BTreeMap<char, usize> = BTreeMap::new();
+        let mut record = |phrase: &str, code: &WubiCode| {
+            if let Some(ch) = single_char(phrase) {
+                let longest = longest.entry(ch).or_default();
+                *longest = code.key_count().max(*longest);
+            }
+        };
+        match &self.reverse {
+            Some(reverse) => {
+                for (phrase, codes) in reverse {
+                    codes.iter().for_each(|code| record(phrase, code));
+                }
+            }
+            None => {
+                for (code, candidates) in &self.codes {
+                    candidates.iter().for_each(|phrase| record(phrase, code));
+                }
+            }
+        }
+        self.codes
+            .iter()
+            .filter_map(|(code, candidates)| {
+                let ch = single_char(candidates.first()?)?;
+                (code.key_count() < *longest.get(&ch)?).then_some((*code, ch))
+            })
+            .collect()
+    }
+}
+
+fn single_char(phrase: &str) -> Option<char> {
+    let mut chars = phrase.chars();
+    chars.next().filter(|_| chars.next().is_none())
+}
+
+/// Candidates of a code that differ between two snapshots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeChange {
+    pub code: WubiCode,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+impl CodeChange {
+    pub fn added(&self) -> impl Iterator<Item = &String> {
+        self.new
+            .iter()
+            .filter(|candidate| !self.old.contains(candidate))
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &String> {
+        self.old
+            .iter()
+            .filter(|candidate| !self.new.contains(candidate))
+    }
+
+    /// Whether the candidates found on both sides are in another order.
+    pub fn reordered(&self) -> bool {
+        let kept = |candidates: &[String], other: &[String]| -> Vec<String> {
+            candidates
+                .iter()
+                .filter(|candidate| other.contains(candidate))
+                .cloned()
+                .collect()
+        };
+        kept(&self.old, &self.new) != kept(&self.new, &self.old)
+    }
+}
+
+/// A simplified code given to another character, added or dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimplifiedChange {
+    pub code: WubiCode,
+    pub old: Option<char>,
+    pub new: Option<char>,
+}
+
+/// Codes of a phrase that differ between two snapshots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReverseChange {
+    pub phrase: String,
+    pub old: Vec<WubiCode>,
+    pub new: Vec<WubiCode>,
+}
+
+/// Differences from an old snapshot to a new one, in code or phrase order.
+///
+/// If either snapshot does not know its simplified codes, those of both are
+/// guessed from the candidates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableDiff {
+    pub codes: Vec<CodeChange>,
+    pub simplified: Vec<SimplifiedChange>,
+    /// `None` if either snapshot has no reverse records.
+    pub reverse: Option<Vec<ReverseChange>>,
+}
+
+impl TableDiff {
+    pub fn new(old: &Snapshot, new: &Snapshot) -> Self {
+        let codes = changes(&old.codes, &new.codes)
+            .map(|(&code, old, new)| CodeChange {
+                code,
+                old: old.cloned().unwrap_or_default(),
+                new: new.cloned().unwrap_or_default(),
+            })
+            .collect();
+        let (old_simplified, new_simplified) = match (&old.simplified, &new.simplified) {
+            (Some(old), Some(new)) => (old.clone(), new.clone()),
+            _ => (old.guessed_simplified(), new.guessed_simplified()),
+        };
+        let simplified = changes(&old_simplified, &new_simplified)
+            .map(|(&code, old, new)| SimplifiedChange {
+                code,
+                old: old.copied(),
+                new: new.copied(),
+            })
+            .collect();
+        let reverse = old
+            .reverse
+            .as_ref()
+            .zip(new.reverse.as_ref())
+            .map(|(old, new)| {
+                changes(old, new)
+                    .map(|(phrase, old, new)| ReverseChange {
+                        phrase: phrase.clone(),
+                        old: old.cloned().unwrap_or_default(),
+                        new: new.cloned().unwrap_or_default(),
+                    })
+                    .collect()
+            });
+        Self {
+            codes,
+            simplified,
+            reverse,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+            && self.simplified.is_empty()
+            && self.reverse.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Value {
+        let codes: Vec<_> = self
+            .codes
+            .iter()
+            .map(|change| {
+                json!({
+                    "code": change.code.to_string(),
+                    "old": change.old,
+                    "new": change.new,
+                    "added": change.added().collect::<Vec<_>>(),
+                    "removed": change.removed().collect::<Vec<_>>(),
+                    "reordered": change.reordered(),
+                })
+            })
+            .collect();
+        let simplified: Vec<_> = self
+            .simplified
+            .iter()
+            .map(|change| {
+                json!({
+                    "code": change.code.to_string(),
+                    "old": change.old,
+                    "new": change.new,
+                })
+            })
+            .collect();
+        let reverse = self.reverse.as_ref().map(|reverse| {
+            reverse
+                .iter()
+                .map(|change| {
+                    json!({
+                        "phrase": change.phrase,
+                        "old": strings(&change.old),
+                        "new": strings(&change.new),
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        json!({ "codes": codes, "simplified": simplified, "reverse": reverse })
+    }
+}
+
+/// Keys whose values differ between `old` and `new`, with both values.
+fn changes<'a, K: Ord, V: PartialEq>(
+    old: &'a BTreeMap<K, V>,
+    new: &'a BTreeMap<K, V>,
+) -> impl Iterator<Item = (&'a K, Option<&'a V>, Option<&'a V>)> {
+    old.iter()
+        .merge_join_by(new, |(a, _), (b, _)| a.cmp(b))
+        .filter_map(|entry| match entry {
+            EitherOrBoth::Left((key, old)) => Some((key, Some(old), None)),
+            EitherOrBoth::Right((key, new)) => Some((key, None, Some(new))),
+            EitherOrBoth::Both((key, old), (_, new)) => {
+                (old != new).then_some((key, Some(old), Some(new)))
+            }
+        })
+}
+
+fn list(items: &[impl ToString]) -> String {
+    match items {
+        [] => "(none)".to_string(),
+        _ => items.iter().map(ToString::to_string).join(" "),
+    }
+}
+
+impl fmt::Display for TableDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "codes: {} changed", self.codes.len())?;
+        for change in &self.codes {
+            let mut parts: Vec<_> = change
+                .added()
+                .map(|candidate| format!("+{candidate}"))
+                .chain(change.removed().map(|candidate| format!("-{candidate}")))
+                .collect();
+            if change.reordered() {
+                parts.push(format!(
+                    "reordered {} -> {}",
+                    list(&change.old),
+                    list(&change.new)
+                ));
+            }
+            write!(f, "\n  {}: {}", change.code, parts.join(", "))?;
+        }
+
+        write!(f, "\nsimplified codes: {} changed", self.simplified.len())?;
+        for change in &self.simplified {
+            write!(
+                f,
+                "\n  {}: {} -> {}",
+                change.code,
+                list(change.old.as_slice()),
+                list(change.new.as_slice())
+            )?;
+        }
+
+        match &self.reverse {
+            Some(reverse) => {
+                write!(f, "\nreverse codes: {} changed", reverse.len())?;
+                for change in reverse {
+                    write!(
+                        f,
+                        "\n  {}: {} -> {}",
+                        change.phrase,
+                        list(&change.old),
+                        list(&change.new)
+                    )?;
+                }
+                Ok(())
+            }
+            None => write!(f, "\nreverse codes: not compared"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::code;
+
+    #[test]
+    fn table_diff() {
+        let table = |simplified_ch: char, phrases: &[(&str, &str)]| {
+            Snapshot::new(&crate::table::testing::table(
+                &[("k", simplified_ch)],
+                phrases,
+            ))
+        };
+        let old = table(
+            '中',
+            &[("中", "khk"), ("吧", "kcn"), ("回", "lkd"), ("囗", "lkd")],
+        );
+        let new = table(
+            '吧',
+            &[
+                ("中", "khk"),
+                ("吧", "kcn"),
+                ("囗", "lkd"),
+                ("回", "lkd"),
+                ("因", "ldi"),
+            ],
+        );
+
+        let diff = TableDiff::new(&old, &new);
+        assert_eq!(diff.simplified.len(), 1);
+        assert_eq!(
+            (diff.simplified[0].old, diff.simplified[0].new),
+            (Some('中'), Some('吧'))
+        );
+        assert_eq!(
+            diff.to_string(),
+            "codes: 3 changed\n  \
+             k: +吧, -中\n  \
+             ldi: +因\n  \
+             lkd: reordered 回 囗 -> 囗 回\n\
+             simplified codes: 1 changed\n  \
+             k: 中 -> 吧\n\
+             reverse codes: 3 changed\n  \
+             中: k khk -> khk\n  \
+             吧: kcn -> k kcn\n  \
+             因: (none) -> ldi"
+        );
+        #[cfg(feature = "json")]
+        assert_eq!(diff.to_json()["codes"][0]["added"], json!(["吧"]));
+        assert!(TableDiff::new(&new, &new).is_empty());
+
+        let records = Snapshot::from_records(old.codes.clone(), None);
+        assert_eq!(records.guessed_simplified(), [(code("k"), '中')].into());
+        let diff = TableDiff::new(&records, &new);
+        assert_eq!(diff.codes.len(), 3);
+        assert_eq!(diff.simplified.len(), 1);
+        assert_eq!(diff.reverse, None);
+    }
+}
@@ -0,0 +1,314 @@
+//! Policies deciding which full-table entries a [`Table`] shows next to the
+//! simplified codes.
+
+use crate::{
+    ParseError,
+    table::{Table, WubiCode},
+};
+use itertools::Itertools as _;
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+/// A rule hiding or reordering entries of the merged table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Filter {
+    /// Under a simplified code, hide the full-table phrases loaded before its
+    /// character, or all of them if the character has no such full code.
+    /// Weights reorder the phrases but do not change what is hidden.
+    HideBeforeSimplified,
+    /// Show the character of a simplified code as its first candidate, even
+    /// if it is also a full-table entry of the code sorted further down.
+    PromoteSimplified,
+    /// In the reverse table, hide a full code of a character that is also
+    /// one of its simplified codes.
+    HideDuplicateCodes,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 3] = [
+        Filter::HideBeforeSimplified,
+        Filter::PromoteSimplified,
+        Filter::HideDuplicateCodes,
+    ];
+
+    /// Name used on the command line.
+    pub fn key(self) -> &'static str {
+        match self {
+            Filter::HideBeforeSimplified => "hide-before-simplified",
+            Filter::PromoteSimplified => "promote-simplified",
+            Filter::HideDuplicateCodes => "hide-duplicate-codes",
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Filter::HideBeforeSimplified => "hide phrases loaded before the simplified character",
+            Filter::PromoteSimplified => "promote the simplified character to first candidate",
+            Filter::HideDuplicateCodes => "hide full codes duplicating simplified codes",
+        })
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Filter::ALL
+            .into_iter()
+            .find(|filter| filter.key() == s)
+            .ok_or_else(|| {
+                let keys = Filter::ALL.into_iter().map(Filter::key).join(", ");
+                ParseError::UnknownName(s.to_string(), keys)
+            })
+    }
+}
+
+/// The set of enabled [`Filter`]s; all of them by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filters {
+    filters: BTreeSet<Filter>,
+}
+
+impl Filters {
+    pub fn all() -> Self {
+        Self::only(Filter::ALL)
+    }
+
+    /// Shows every entry.
+    pub fn none() -> Self {
+        Self::only([])
+    }
+
+    pub fn only(filters: impl IntoIterator<Item = Filter>) -> Self {
+        Self {
+            filters: filters.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, filter: Filter) -> bool {
+        self.filters.contains(&filter)
+    }
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromStr for Filters {
+    type Err = ParseError;
+
+    /// Parses `default`, `keep-all` or a comma-separated list of
+    /// [`Filter::key`]s.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::all()),
+            "keep-all" => Ok(Self::none()),
+            _ => s
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map(|filters| Self { filters }),
+        }
+    }
+}
+
+/// What the enabled filters of a table hide or move, as built by
+/// [`FilterReport::new`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterReport {
+    pub filters: Filters,
+    /// Phrases hidden by [`Filter::HideBeforeSimplified`].
+    pub hidden_phrases: Vec<(WubiCode, String)>,
+    /// Characters moved up by [`Filter::PromoteSimplified`].
+    pub promoted: Vec<(WubiCode, char)>,
+    /// Codes hidden by [`Filter::HideDuplicateCodes`].
+    pub hidden_codes: Vec<(char, WubiCode)>,
+}
+
+impl FilterReport {
+    pub fn new(table: &Table) -> Self {
+        let filters = table.filters();
+        let mut report = Self {
+            filters: filters.clone(),
+            ..Self::default()
+        };
+        for (code, ch) in table.simplified_table() {
+            if filters.contains(Filter::HideBeforeSimplified) {
+                report.hidden_phrases.extend(
+                    table
+                        .full()
+                        .phrases(&code)
+                        .iter()
+                        .filter(|phrase| table.loaded_before_simplified(&code, phrase))
+                        .map(|phrase| (code, phrase.clone())),
+                );
+            }
+            if filters.contains(Filter::PromoteSimplified)
+                && table
+                    .filtered_phrases(&code)
+                    .iter()
+                    .position(|phrase| phrase.chars().eq([ch]))
+                    .is_some_and(|position| position > 0)
+            {
+                report.promoted.push((code, ch));
+            }
+        }
+        if filters.contains(Filter::HideDuplicateCodes) {
+            for (ch, simplified_codes) in table.reverse_simplified_table() {
+                let simplified_codes: Vec<_> = simplified_codes.collect();
+                report.hidden_codes.extend(
+                    table
+                        .full()
+                        .codes(&ch.to_string())
+                        .iter()
+                        .filter(|code| simplified_codes.contains(code))
+                        .map(|&code| (ch, code)),
+                );
+            }
+        }
+        report
+    }
+}
+
+impl FilterReport {
+    /// What `filter` did, one line per entry, and how to name the entries.
+    fn entries(&self, filter: Filter) -> (Vec<String>, &'static str) {
+        match filter {
+            Filter::HideBeforeSimplified => (
+                self.hidden_phrases
+                    .iter()
+                    .map(|(code, phrase)| format!("{code} {phrase}"))
+                    .collect(),
+                "phrase(s) hidden",
+            ),
+            Filter::PromoteSimplified => (
+                self.promoted
+                    .iter()
+                    .map(|(code, ch)| format!("{code} {ch}"))
+                    .collect(),
+                "character(s) promoted",
+            ),
+            Filter::HideDuplicateCodes => (
+                self.hidden_codes
+                    .iter()
+                    .map(|(ch, code)| format!("{ch} {code}"))
+                    .collect(),
+                "code(s) hidden",
+            ),
+        }
+    }
+}
+
+/// Writes a count per filter; the alternate form (`{:#}`) also lists the
+/// entries.
+impl fmt::Display for FilterReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, filter) in Filter::ALL.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if !self.filters.contains(filter) {
+                write!(f, "{}: off", filter.key())?;
+                continue;
+            }
+            let (entries, what) = self.entries(filter);
+            write!(f, "{}: {} {what}", filter.key(), entries.len())?;
+            if f.alternate() {
+                for entry in entries {
+                    write!(f, "\n  {entry}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        WubiEntry,
+        table::{
+            SimplifiedCodeTable,
+            testing::{code, full},
+        },
+    };
+
+    #[test]
+    fn filter_policies() {
+        let table = |filters: &str| {
+            crate::table::testing::table(
+                &[("xca", '经')],
+                &[("纟", "xca"), ("经", "xca"), ("经", "xcag")],
+            )
+            .with_filters(filters.parse().unwrap())
+        };
+        let lookup = |table: &Table| -> Vec<_> {
+            table
+                .lookup(&code("xca"))
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        let reverse = |table: &Table| {
+            table
+                .reverse_entries()
+                .find(|(phrase, _)| phrase == "经")
+                .unwrap()
+                .1
+        };
+
+        let filtered = table("default");
+        assert_eq!(lookup(&filtered), ["经"]);
+        assert_eq!(reverse(&filtered), [code("xca"), code("xcag")]);
+        let report = FilterReport::new(&filtered);
+        assert_eq!(report.hidden_phrases, [(code("xca"), "纟".to_string())]);
+        assert_eq!(report.hidden_codes, [('经', code("xca"))]);
+
+        let kept = table("keep-all");
+        assert_eq!(lookup(&kept), ["纟", "经"]);
+        assert_eq!(reverse(&kept), [code("xca"), code("xcag")]);
+        assert_eq!(
+            FilterReport::new(&kept).to_string(),
+            "hide-before-simplified: off\npromote-simplified: off\nhide-duplicate-codes: off"
+        );
+
+        let promoted = table("promote-simplified");
+        assert_eq!(lookup(&promoted), ["经", "纟"]);
+        assert_eq!(FilterReport::new(&promoted).promoted, [(code("xca"), '经')]);
+
+        let mut simplified = SimplifiedCodeTable::new();
+        simplified.insert(&code("agn"), '七').unwrap();
+        let mut full = full(&[("七", "agn")]);
+        full.insert(WubiEntry::new("匚".into(), code("agn")).with_weight(Some(100)))
+            .unwrap();
+        full.insert(WubiEntry::new("匸".into(), code("agn")))
+            .unwrap();
+        let weighted = Table::new(simplified, full);
+        let lookup = |table: &Table| -> Vec<_> {
+            table
+                .lookup(&code("agn"))
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        assert_eq!(lookup(&weighted), ["七", "匚", "匸"]);
+        let report = FilterReport::new(&weighted);
+        assert!(report.hidden_phrases.is_empty());
+        assert_eq!(report.promoted, [(code("agn"), '七')]);
+        let weighted = weighted.with_filters("hide-before-simplified".parse().unwrap());
+        assert_eq!(lookup(&weighted), ["匚", "七", "匸"]);
+        assert!(FilterReport::new(&weighted).promoted.is_empty());
+        assert_eq!(
+            "promote-simplified,hide-everything"
+                .parse::<Filters>()
+                .unwrap_err()
+                .to_string(),
+            "Unknown name \"hide-everything\", expected one of: hide-before-simplified, \
+             promote-simplified, hide-duplicate-codes"
+        );
+    }
+}
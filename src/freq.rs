@@ -0,0 +1,132 @@
+//! Character and phrase frequencies counted from a corpus.
+
+use crate::table::FullCodeTable;
+use itertools::Itertools as _;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+/// Counts occurrences of the characters of a [`FullCodeTable`] and of its
+/// phrases, splitting text by longest match against the phrases.
+pub struct Frequencies<'a> {
+    full: &'a FullCodeTable,
+    /// Length in characters of the longest phrase.
+    longest: usize,
+    counts: HashMap<String, u64>,
+}
+
+impl<'a> Frequencies<'a> {
+    pub fn new(full: &'a FullCodeTable) -> Self {
+        let longest = full
+            .entries()
+            .map(|(phrase, _)| phrase.chars().count())
+            .max()
+            .unwrap_or_default();
+        Self {
+            full,
+            longest,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Counts every character of `text` that is in the table, and the phrases
+    /// of `text`: at each position the longest phrase of the table is taken,
+    /// and text matching no phrase is skipped a character at a time.
+    pub fn count(&mut self, text: &str) {
+        let mut buf = [0; 4];
+        for ch in text.chars() {
+            let ch = &*ch.encode_utf8(&mut buf);
+            if !self.full.codes(ch).is_empty() {
+                self.add(ch);
+            }
+        }
+
+        let bounds: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .collect();
+        let mut start = 0;
+        while start + 1 < bounds.len() {
+            let longest = self.longest.min(bounds.len() - 1 - start);
+            let matched = (2..=longest).rev().find(|&len| {
+                !self
+                    .full
+                    .codes(&text[bounds[start]..bounds[start + len]])
+                    .is_empty()
+            });
+            match matched {
+                Some(len) => {
+                    self.add(&text[bounds[start]..bounds[start + len]]);
+                    start += len;
+                }
+                None => start += 1,
+            }
+        }
+    }
+
+    fn add(&mut self, phrase: &str) {
+        match self.counts.get_mut(phrase) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(phrase.to_string(), 1);
+            }
+        }
+    }
+
+    /// Number of times `phrase` was counted.
+    pub fn get(&self, phrase: &str) -> u64 {
+        self.counts.get(phrase).copied().unwrap_or_default()
+    }
+
+    /// Writes a `phrase<TAB>count` line for every phrase seen, most frequent
+    /// first, in the format read by [`load_weights`](crate::load::load_weights).
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let counts = self
+            .counts
+            .iter()
+            .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        for (phrase, count) in counts {
+            writeln!(
+                out,
+                "{phrase}\t{}",
+                u32::try_from(*count).unwrap_or(u32::MAX)
+            )?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::full;
+
+    #[test]
+    fn longest_match() {
+        let full = full(&[
+            ("中", "khk"),
+            ("国", "lgyi"),
+            ("人", "wwww"),
+            ("中国", "khlg"),
+            ("中国人", "klww"),
+        ]);
+        let mut frequencies = Frequencies::new(&full);
+        frequencies.count("中国人民，中国。");
+        frequencies.count("国人");
+        assert_eq!(frequencies.get("中国人"), 1);
+        assert_eq!(frequencies.get("中国"), 1);
+        assert_eq!(frequencies.get("国"), 3);
+        assert_eq!(frequencies.get("人"), 2);
+        assert_eq!(frequencies.get("中"), 2);
+        assert_eq!(frequencies.get("民"), 0);
+
+        let mut out = Vec::new();
+        frequencies.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "国\t3\n中\t2\n人\t2\n中国\t1\n中国人\t1\n"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Wubi (五笔) code table builder.
+//!
+//! The crate loads a full-code table of single characters, derives codes for
+//! phrases from it, merges in the simplified (简码) codes and exposes the
+//! resulting [`Table`] for output generation or lookup.
+
+#![forbid(unsafe_code)]
+
+use thiserror::Error;
+
+pub mod analysis;
+pub mod block;
+pub mod diff;
+pub mod filter;
+pub mod freq;
+pub mod load;
+pub mod output;
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod rule;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod table;
+
+pub use block::{Block, BlockFilter};
+pub use filter::{Filter, FilterReport, Filters};
+pub use load::{
+    Diagnostics, LoadError, LoadOptions, SingleChars, load_full, load_full_with, load_phrases,
+    load_phrases_with, load_rules, load_simplified, load_simplified_with, load_snapshot,
+    load_weights,
+};
+pub use rule::{CharSource, PhraseDerivation, PhraseRule, PhraseRules};
+pub use table::{
+    Candidate, Candidates, CodeChoice, CodeKind, FullCodeTable, PhraseCode, SimplifiedCodeTable,
+    Table, WubiCode, derive_code_for_phrase, get_code_for_phrase,
+};
+
+// TODO: refuse 16-bits computer
+
+/// Error produced while parsing a single line or code.
+#[derive(Error, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[error("empty")]
+    Empty,
+    #[error("too long code: {0:?}")]
+    TooLongCode(Vec<u8>),
+    #[error("No '\\t' found: {0}")]
+    NoTabFound(String),
+    #[error("More than one character found: {0}")]
+    MultipleCharacters(String),
+    #[error("Not ASCII lowercase")]
+    NotValidChar,
+    #[error("Invalid format")]
+    Invalid,
+    #[error("Parse int error: {0}")]
+    ParseInt(#[from] std::num::ParseIntError),
+    #[error("Codepoint does not match character")]
+    CodepointMismatch,
+    #[error("Duplicate phrase: {0}")]
+    DuplicatePhrase(String),
+    #[error("Code already assigned to {0}")]
+    CodeTaken(char),
+    #[error("Too many simplified codes for {0}")]
+    TooManyCodes(char),
+    #[error("Character not in full table: {0}")]
+    MissingCharacter(char),
+    #[error("Phrase of a single character: {0}")]
+    SingleCharacter(char),
+    #[error("No rule for phrases of {0} characters")]
+    NoRule(usize),
+    #[error("Invalid weight: {0}")]
+    InvalidWeight(String),
+    /// A rule takes letter `.1` (from 1) of the code of `.0`, which is shorter.
+    #[error("Code of {0} has no letter {1}")]
+    MissingLetter(char, usize),
+    /// `.0` is none of the names listed in `.1`.
+    #[error("Unknown name {0:?}, expected one of: {1}")]
+    UnknownName(String, String),
+}
+
+impl ParseError {
+    /// Short description of the kind of problem, used to group reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::Empty => "empty line or code",
+            ParseError::TooLongCode(_) => "code too long",
+            ParseError::NoTabFound(_) => "missing tab",
+            ParseError::MultipleCharacters(_) => "more than one character",
+            ParseError::NotValidChar => "invalid letters",
+            ParseError::Invalid => "invalid format",
+            ParseError::ParseInt(_) => "invalid codepoint",
+            ParseError::CodepointMismatch => "codepoint mismatch",
+            ParseError::DuplicatePhrase(_) => "duplicate phrase",
+            ParseError::CodeTaken(_) => "duplicate simplified code",
+            ParseError::TooManyCodes(_) => "too many simplified codes",
+            ParseError::MissingCharacter(_) => "character missing from full table",
+            ParseError::SingleCharacter(_) => "single-character phrase",
+            ParseError::NoRule(_) => "no rule for phrase length",
+            ParseError::InvalidWeight(_) => "invalid weight",
+            ParseError::MissingLetter(..) => "letter missing from character code",
+            ParseError::UnknownName(..) => "unknown name",
+        }
+    }
+}
+
+/// A phrase (or single character) together with its full code and, if
+/// known, its weight (frequency).
+#[derive(Clone, Debug)]
+pub struct WubiEntry {
+    phrase: String,
+    wubi_code: WubiCode,
+    weight: Option<u32>,
+}
+
+impl WubiEntry {
+    pub fn new(phrase: String, wubi_code: WubiCode) -> Self {
+        Self {
+            phrase,
+            wubi_code,
+            weight: None,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: Option<u32>) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    pub fn wubi_code(&self) -> WubiCode {
+        self.wubi_code
+    }
+
+    pub fn weight(&self) -> Option<u32> {
+        self.weight
+    }
+
+    pub fn into_parts(self) -> (String, WubiCode) {
+        (self.phrase, self.wubi_code)
+    }
+}
+
+/// Parses an optional `<TAB>weight` column at the end of `line`, returning the
+/// rest of the line and the weight.
+pub(crate) fn split_weight(line: &str) -> Result<(&str, Option<u32>), ParseError> {
+    match line.rsplit_once('\t') {
+        Some((rest, weight)) => match weight.parse() {
+            Ok(weight) => Ok((rest, Some(weight))),
+            Err(_) => Err(ParseError::InvalidWeight(weight.to_string())),
+        },
+        None => Ok((line, None)),
+    }
+}
+
+/// `items` as strings, e.g. for a JSON array.
+#[cfg(feature = "json")]
+pub(crate) fn strings(items: &[impl ToString]) -> Vec<String> {
+    items.iter().map(ToString::to_string).collect()
+}
+
+/// Parses a `U+XXXX<TAB>字<TAB>code` line as found in `CJK.txt`, optionally
+/// followed by `<TAB>weight`.
+pub fn parse_line_with_codepoint(line: &str) -> Result<WubiEntry, ParseError> {
+    let (codepoint, rest) = line
+        .split_once('\t')
+        .ok_or(ParseError::NoTabFound(line.to_string()))?;
+    let (ch, wubi) = rest
+        .split_once('\t')
+        .ok_or(ParseError::NoTabFound(line.to_string()))?;
+    let (wubi, weight) = split_weight(wubi)?;
+    let ch = {
+        if ch.chars().count() != 1 {
+            return Err(ParseError::MultipleCharacters(ch.to_string()));
+        }
+        ch.chars().next().expect("Checked above")
+    };
+    if !(codepoint.starts_with("U+") && u32::from_str_radix(&codepoint[2..], 16)? == ch as u32) {
+        return Err(ParseError::CodepointMismatch);
+    }
+    Ok(WubiEntry {
+        phrase: ch.to_string(),
+        wubi_code: wubi.try_into()?,
+        weight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_codepoint_line() {
+        let entry = parse_line_with_codepoint("U+4E00\t一\tggll").unwrap();
+        assert_eq!(entry.phrase(), "一");
+        assert_eq!(entry.wubi_code(), WubiCode::try_from("ggll").unwrap());
+        assert_eq!(
+            parse_line_with_codepoint("U+4E01\t一\tggll").unwrap_err(),
+            ParseError::CodepointMismatch
+        );
+        assert_eq!(
+            parse_line_with_codepoint("U+4E00\t一\tggllg").unwrap_err(),
+            ParseError::TooLongCode(b"ggllg".to_vec())
+        );
+        assert_eq!(
+            parse_line_with_codepoint("U+4E00\t一\tgz").unwrap_err(),
+            ParseError::NotValidChar
+        );
+        let entry = parse_line_with_codepoint("U+4E00\t一\tggll\t2500").unwrap();
+        assert_eq!(entry.wubi_code(), WubiCode::try_from("ggll").unwrap());
+        assert_eq!(entry.weight(), Some(2500));
+        assert_eq!(
+            parse_line_with_codepoint("U+4E00\t一\tggll\tmany").unwrap_err(),
+            ParseError::InvalidWeight("many".to_string())
+        );
+    }
+}
@@ -0,0 +1,558 @@
+//! Loaders for the plain-text source files.
+
+use crate::{
+    ParseError, WubiEntry,
+    block::{Block, BlockFilter},
+    diff::Snapshot,
+    parse_line_with_codepoint,
+    rule::{PhraseDerivation, PhraseRules},
+    split_weight,
+    table::{CodeChoice, FullCodeTable, SimplifiedCodeTable, WubiCode},
+};
+use itertools::Itertools as _;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    io::{self, BufRead as _},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Error produced while loading a source file.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum LoadError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{}:{line}: {source}: {text:?}", path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        text: String,
+        #[source]
+        source: ParseError,
+    },
+}
+
+impl LoadError {
+    /// Short description of the kind of problem, used to group reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LoadError::Io { .. } => "I/O error",
+            LoadError::Parse { source, .. } => source.kind(),
+        }
+    }
+
+    fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+}
+
+/// A line of a source file, kept around to report errors against it.
+struct Line<'a> {
+    path: &'a Path,
+    number: usize,
+    text: String,
+}
+
+impl Line<'_> {
+    fn error(&self, source: ParseError) -> LoadError {
+        LoadError::Parse {
+            path: self.path.to_owned(),
+            line: self.number,
+            text: self.text.clone(),
+            source,
+        }
+    }
+}
+
+fn lines(path: &Path) -> Result<impl Iterator<Item = Result<Line<'_>, LoadError>>, LoadError> {
+    let file = fs::File::open(path).map_err(|e| LoadError::io(path, e))?;
+    Ok(io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .map(move |(index, text)| {
+            Ok(Line {
+                path,
+                number: index + 1,
+                text: text.map_err(|e| LoadError::io(path, e))?,
+            })
+        }))
+}
+
+fn parse_simplified_line(line: &str) -> Result<(char, WubiCode), ParseError> {
+    let (chars, code) = line
+        .split_once('\t')
+        .ok_or(ParseError::NoTabFound(line.to_string()))?;
+    let mut chars_iter = chars.chars();
+    let ch = chars_iter.next().ok_or(ParseError::Empty)?;
+    if chars_iter.next().is_some() {
+        return Err(ParseError::MultipleCharacters(chars.to_string()));
+    }
+    Ok((ch, code.try_into()?))
+}
+
+/// Callback deciding what happens to a bad line: returning the error aborts
+/// loading, returning `Ok(())` skips the line and continues.
+pub type OnError<'a> = dyn FnMut(LoadError) -> Result<(), LoadError> + 'a;
+
+/// Options for the `*_with` loaders.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct LoadOptions {
+    /// Blocks whose characters are loaded; entries and phrases with other
+    /// characters are skipped.
+    pub blocks: BlockFilter,
+    /// Which code of a character with several full codes to derive from.
+    pub code_choice: CodeChoice,
+    /// Rules deriving phrase codes from character codes.
+    pub rules: PhraseRules,
+    /// What the phrase loader does with a line holding a single character.
+    pub single_chars: SingleChars,
+    /// Weights of characters and phrases whose source line has none, as
+    /// read by [`load_weights`].
+    pub weights: HashMap<String, u32>,
+}
+
+/// Handling of single characters in the phrase list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SingleChars {
+    /// Report them as [`ParseError::SingleCharacter`].
+    #[default]
+    Reject,
+    /// Accept them as their own full-table entries, which must exist; a
+    /// weight column sets the weight of the entry.
+    Full,
+}
+
+impl SingleChars {
+    pub const ALL: [SingleChars; 2] = [SingleChars::Reject, SingleChars::Full];
+
+    /// Name used on the command line.
+    pub fn key(self) -> &'static str {
+        match self {
+            SingleChars::Reject => "reject",
+            SingleChars::Full => "full",
+        }
+    }
+}
+
+impl FromStr for SingleChars {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SingleChars::ALL
+            .into_iter()
+            .find(|single_chars| single_chars.key() == s)
+            .ok_or_else(|| {
+                let keys = SingleChars::ALL
+                    .into_iter()
+                    .map(SingleChars::key)
+                    .join(", ");
+                ParseError::UnknownName(s.to_string(), keys)
+            })
+    }
+}
+
+/// Loads `字<TAB>code` lines (`simplified*.txt`) into `simplified`.
+pub fn load_simplified(simplified: &mut SimplifiedCodeTable, path: &Path) -> Result<(), LoadError> {
+    load_simplified_with(simplified, path, &LoadOptions::default(), &mut Err)
+}
+
+/// Like [`load_simplified`], passing every bad line to `on_error`.
+pub fn load_simplified_with(
+    simplified: &mut SimplifiedCodeTable,
+    path: &Path,
+    options: &LoadOptions,
+    on_error: &mut OnError,
+) -> Result<(), LoadError> {
+    for line in lines(path)? {
+        let line = line?;
+        let result = parse_simplified_line(&line.text).and_then(|(ch, code)| {
+            if options.blocks.contains(Block::of(ch)) {
+                simplified.insert(&code, ch)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            on_error(line.error(e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads `U+XXXX<TAB>字<TAB>code` lines (`CJK.txt`) into `full`.
+pub fn load_full(full: &mut FullCodeTable, path: &Path) -> Result<(), LoadError> {
+    load_full_with(full, path, &LoadOptions::default(), &mut Err)
+}
+
+/// Like [`load_full`], passing every bad line to `on_error`.
+pub fn load_full_with(
+    full: &mut FullCodeTable,
+    path: &Path,
+    options: &LoadOptions,
+    on_error: &mut OnError,
+) -> Result<(), LoadError> {
+    for line in lines(path)? {
+        let line = line?;
+        let result = parse_line_with_codepoint(&line.text).and_then(|entry| {
+            if options.blocks.accepts(entry.phrase()) {
+                let weight = entry
+                    .weight()
+                    .or_else(|| options.weights.get(entry.phrase()).copied());
+                full.insert(entry.with_weight(weight))
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            on_error(line.error(e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Derives the code of a phrase of at least two characters of `full`.
+pub fn encode_phrase(
+    full: &FullCodeTable,
+    phrase: &str,
+    options: &LoadOptions,
+) -> Result<WubiCode, ParseError> {
+    derive_phrase(full, phrase, options).map(|derivation| derivation.code())
+}
+
+/// Like [`encode_phrase`], explaining how the code was derived.
+pub fn derive_phrase<'a>(
+    full: &FullCodeTable,
+    phrase: &str,
+    options: &'a LoadOptions,
+) -> Result<PhraseDerivation<'a>, ParseError> {
+    let mut chars = phrase.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => return Err(ParseError::Empty),
+        (Some(ch), None) => return Err(ParseError::SingleCharacter(ch)),
+        _ => {}
+    }
+    let char_code = |ch: char| full.canonical_code(&ch.to_string(), options.code_choice);
+    if let Some(ch) = phrase.chars().find(|&ch| char_code(ch).is_none()) {
+        return Err(ParseError::MissingCharacter(ch));
+    }
+    options.rules.derive(phrase, char_code)
+}
+
+/// Loads one phrase per line (`phrases.txt`), optionally followed by
+/// `<TAB>weight`, deriving each code from the characters already present in
+/// `full`. Blank lines are skipped.
+pub fn load_phrases(full: &mut FullCodeTable, path: &Path) -> Result<(), LoadError> {
+    load_phrases_with(full, path, &LoadOptions::default(), &mut Err)
+}
+
+/// Like [`load_phrases`], passing every bad line to `on_error`.
+pub fn load_phrases_with(
+    full: &mut FullCodeTable,
+    path: &Path,
+    options: &LoadOptions,
+    on_error: &mut OnError,
+) -> Result<(), LoadError> {
+    for line in lines(path)? {
+        let line = line?;
+        if line.text.trim().is_empty() {
+            continue;
+        }
+        if let Err(e) = insert_phrase(full, &line.text, options) {
+            on_error(line.error(e))?;
+        }
+    }
+    Ok(())
+}
+
+fn insert_phrase(
+    full: &mut FullCodeTable,
+    line: &str,
+    options: &LoadOptions,
+) -> Result<(), ParseError> {
+    let (phrase, weight) = split_weight(line)?;
+    if !options.blocks.accepts(phrase) {
+        return Ok(());
+    }
+    let mut chars = phrase.chars();
+    match (chars.next(), chars.next(), options.single_chars) {
+        (Some(ch), None, SingleChars::Full) => match full.codes(phrase) {
+            [] => Err(ParseError::MissingCharacter(ch)),
+            _ => {
+                if let Some(weight) = weight {
+                    full.set_weight(phrase, weight);
+                }
+                Ok(())
+            }
+        },
+        _ => {
+            let wubi_code = encode_phrase(full, phrase, options)?;
+            let weight = weight.or_else(|| options.weights.get(phrase).copied());
+            full.insert(WubiEntry::new(phrase.to_string(), wubi_code).with_weight(weight))
+        }
+    }
+}
+
+/// Loads phrase rules, one per line (see [`PhraseRules`]), replacing the
+/// standard ones.
+pub fn load_rules(path: &Path) -> Result<PhraseRules, LoadError> {
+    let mut rules = Vec::new();
+    for line in lines(path)? {
+        let line = line?;
+        let text = line.text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        rules.push(text.parse().map_err(|e| line.error(e))?);
+    }
+    Ok(PhraseRules::new(rules))
+}
+
+/// Loads `phrase<TAB>weight` lines, as written by
+/// [`Frequencies::write`](crate::freq::Frequencies::write); blank lines are
+/// skipped.
+pub fn load_weights(path: &Path) -> Result<HashMap<String, u32>, LoadError> {
+    let mut weights = HashMap::new();
+    for line in lines(path)? {
+        let line = line?;
+        if line.text.trim().is_empty() {
+            continue;
+        }
+        match split_weight(&line.text) {
+            Ok((phrase, Some(weight))) => {
+                weights.insert(phrase.to_string(), weight);
+            }
+            Ok((_, None)) => {
+                return Err(line.error(ParseError::NoTabFound(line.text.clone())));
+            }
+            Err(e) => return Err(line.error(e)),
+        }
+    }
+    Ok(weights)
+}
+
+/// Loads a previously generated table: `wb_nc_table.txt` (`code phrase...`
+/// lines) and, if given, `wb_nc_reverse_table.txt` (`phrase code...` lines).
+///
+/// The values of a repeated code or phrase are appended to those of its
+/// first line, skipping the ones already listed.
+pub fn load_snapshot(table: &Path, reverse: Option<&Path>) -> Result<Snapshot, LoadError> {
+    let mut codes = BTreeMap::new();
+    for line in lines(table)? {
+        let line = line?;
+        let mut fields = line.text.split(' ');
+        let code = fields.next().unwrap_or_default();
+        let code = WubiCode::try_from(code).map_err(|e| line.error(e))?;
+        let candidates: Vec<_> = fields.map(str::to_string).collect();
+        if candidates.is_empty() {
+            return Err(line.error(ParseError::Invalid));
+        }
+        merge(codes.entry(code).or_default(), candidates);
+    }
+    let reverse = match reverse {
+        Some(path) => {
+            let mut reverse = BTreeMap::new();
+            for line in lines(path)? {
+                let line = line?;
+                let mut fields = line.text.split(' ');
+                let phrase = fields.next().unwrap_or_default().to_string();
+                let codes = fields
+                    .map(WubiCode::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| line.error(e))?;
+                if codes.is_empty() {
+                    return Err(line.error(ParseError::Invalid));
+                }
+                merge(reverse.entry(phrase).or_default(), codes);
+            }
+            Some(reverse)
+        }
+        None => None,
+    };
+    Ok(Snapshot::from_records(codes, reverse))
+}
+
+fn merge<T: PartialEq>(values: &mut Vec<T>, more: Vec<T>) {
+    for value in more {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+}
+
+/// Every problem found by the `*_with` loaders, for a collect-all check.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<LoadError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `error` and lets loading continue.
+    pub fn push(&mut self, error: LoadError) -> Result<(), LoadError> {
+        self.errors.push(error);
+        Ok(())
+    }
+
+    pub fn errors(&self) -> &[LoadError] {
+        &self.errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    /// Writes the problems grouped by kind, each group headed by its count.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = self.errors.iter().into_group_map_by(|e| e.kind());
+        for (kind, errors) in groups.into_iter().sorted_by_key(|(kind, _)| *kind) {
+            writeln!(f, "{kind} ({})", errors.len())?;
+            for error in errors {
+                writeln!(f, "  {error}")?;
+            }
+        }
+        write!(f, "{} problem(s) found", self.errors.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::{code, full};
+
+    #[test]
+    fn error_context() {
+        let path = std::env::temp_dir().join(format!("wubi-simplified-{}.txt", std::process::id()));
+        fs::write(&path, "一\tg\n丁 sgh\n").unwrap();
+        let e = load_simplified(&mut SimplifiedCodeTable::new(), &path).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("{}:2: No '\\t' found: 丁 sgh: \"丁 sgh\"", path.display())
+        );
+        fs::remove_file(&path).unwrap();
+
+        let e = load_simplified(&mut SimplifiedCodeTable::new(), &path).unwrap_err();
+        assert_eq!(e.kind(), "I/O error");
+        assert!(e.to_string().starts_with(&format!("{}: ", path.display())));
+    }
+
+    #[test]
+    fn grouped_diagnostics() {
+        let path = std::env::temp_dir().join(format!("wubi-check-{}.txt", std::process::id()));
+        fs::write(&path, "一\tg\n丁 sgh\n二\tfgz\n七\tg\n丂\n").unwrap();
+        let mut diagnostics = Diagnostics::new();
+        load_simplified_with(
+            &mut SimplifiedCodeTable::new(),
+            &path,
+            &LoadOptions::default(),
+            &mut |e| diagnostics.push(e),
+        )
+        .unwrap();
+        assert_eq!(
+            diagnostics.to_string(),
+            format!(
+                "duplicate simplified code (1)\n  \
+                 {path}:4: Code already assigned to 一: \"七\\tg\"\n\
+                 invalid letters (1)\n  \
+                 {path}:3: Not ASCII lowercase: \"二\\tfgz\"\n\
+                 missing tab (2)\n  \
+                 {path}:2: No '\\t' found: 丁 sgh: \"丁 sgh\"\n  \
+                 {path}:5: No '\\t' found: 丂: \"丂\"\n\
+                 4 problem(s) found",
+                path = path.display()
+            )
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn single_char_phrases() {
+        let path = std::env::temp_dir().join(format!("wubi-phrases-{}.txt", std::process::id()));
+        fs::write(&path, "中国\n\n中\t500\n华\n").unwrap();
+        let mut options = LoadOptions::default();
+        let load = |options: &LoadOptions| {
+            let mut full = full(&[("中华", "khk"), ("中", "khk"), ("国", "lgyi")]);
+            let mut diagnostics = Diagnostics::new();
+            load_phrases_with(&mut full, &path, options, &mut |e| diagnostics.push(e)).unwrap();
+            let kinds: Vec<_> = diagnostics.errors().iter().map(LoadError::kind).collect();
+            (full, kinds)
+        };
+
+        let (_, kinds) = load(&options);
+        assert_eq!(
+            kinds,
+            ["single-character phrase", "single-character phrase"]
+        );
+
+        options.single_chars = SingleChars::Full;
+        let (full, kinds) = load(&options);
+        assert_eq!(kinds, ["character missing from full table"]);
+        assert_eq!(full.phrases(&code("khlg")), ["中国"]);
+        assert_eq!(full.phrases(&code("khk")), ["中", "中华"]);
+        assert_eq!(full.weight("中"), Some(500));
+        assert_eq!("full".parse(), Ok(SingleChars::Full));
+        assert_eq!(
+            "accept".parse::<SingleChars>().unwrap_err().to_string(),
+            "Unknown name \"accept\", expected one of: reject, full"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn weights_with_blank_lines() {
+        let path = std::env::temp_dir().join(format!("wubi-weights-{}.txt", std::process::id()));
+        fs::write(&path, "中\t30\n\n中国\t8\n  \n").unwrap();
+        let weights = load_weights(&path).unwrap();
+        assert_eq!(
+            weights,
+            [("中".to_string(), 30), ("中国".to_string(), 8)].into()
+        );
+
+        fs::write(&path, "中\t30\n中国\n").unwrap();
+        let e = load_weights(&path).unwrap_err();
+        assert_eq!(e.kind(), "missing tab");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn repeated_generated_lines() {
+        let dir = std::env::temp_dir();
+        let table = dir.join(format!("wubi-table-{}.txt", std::process::id()));
+        let reverse = dir.join(format!("wubi-reverse-{}.txt", std::process::id()));
+        fs::write(&table, "g 一\nggll 一\ngg 一\ng 一 弋\n").unwrap();
+        fs::write(&reverse, "一 g gg\n一 gg ggll\n").unwrap();
+
+        let snapshot = load_snapshot(&table, Some(&reverse)).unwrap();
+        let codes = [
+            (code("g"), vec!["一".to_string(), "弋".to_string()]),
+            (code("gg"), vec!["一".to_string()]),
+            (code("ggll"), vec!["一".to_string()]),
+        ];
+        let phrases = [("一".to_string(), vec![code("g"), code("gg"), code("ggll")])];
+        assert_eq!(
+            snapshot,
+            Snapshot::from_records(codes.into(), Some(phrases.into()))
+        );
+        fs::remove_file(table).unwrap();
+        fs::remove_file(reverse).unwrap();
+    }
+}
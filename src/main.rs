@@ -1,231 +1,592 @@
 #![forbid(unsafe_code)]
 
+use clap::{
+    ArgAction, Args, Parser, Subcommand, ValueEnum,
+    builder::{PossibleValuesParser, TypedValueParser as _},
+};
 use std::{
-    cmp::Ordering,
+    error::Error,
     fs,
-    io::{self, BufRead as _, Write as _},
+    io::{self, BufRead as _, IsTerminal as _, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
-use table::*;
-use thiserror::Error;
-
-pub mod table;
-
-// TODO: refuse 16-bits computer
-
-#[derive(Error, Debug, PartialEq)]
-#[non_exhaustive]
-pub enum ParseError {
-    #[error("empty")]
-    Empty,
-    #[error("too long code: {0:?}")]
-    TooLongCode(Vec<u8>),
-    #[error("No '\\t' found: {0}")]
-    NoTabFound(String),
-    #[error("More than one character found: {0}")]
-    MultipleCharacters(String),
-    #[error("Not ASCII lowercase")]
-    NotValidChar,
-    #[error("Invalid format")]
-    Invalid,
-    #[error("Parse int error: {0}")]
-    ParseInt(#[from] std::num::ParseIntError),
-    #[error("Codepoint does not match character")]
-    CodepointMismatch,
-}
-
-#[derive(Clone, Debug)]
-pub struct WubiEntry {
-    phrase: String,
-    wubi_code: WubiCode,
-}
-
-fn parse_line_with_codepoint(line: &str) -> Result<WubiEntry, ParseError> {
-    let (codepoint, rest) = line
-        .split_once('\t')
-        .ok_or(ParseError::NoTabFound(line.to_string()))?;
-    let (ch, wubi) = rest
-        .split_once('\t')
-        .ok_or(ParseError::NoTabFound(line.to_string()))?;
-    let ch = {
-        if ch.chars().count() != 1 {
-            return Err(ParseError::MultipleCharacters(ch.to_string()));
-        }
-        ch.chars().next().expect("Checked above")
-    };
-    if !(codepoint.starts_with("U+") && u32::from_str_radix(&codepoint[2..], 16)? == ch as u32) {
-        return Err(ParseError::CodepointMismatch);
-    }
-    let mut cnt = 0;
-    for b in wubi.as_bytes() {
-        if !(b'a'..=b'y').contains(b) {
-            return Err(ParseError::NotValidChar);
-        }
-        cnt += 1; // TODO: check overflow
-    }
-    if !(1..=4).contains(&cnt) {
-        return Err(ParseError::Invalid);
-    }
-    Ok(WubiEntry {
-        phrase: ch.to_string(),
-        wubi_code: wubi.try_into()?,
-    })
+use wubi_table::{
+    analysis::CollisionReport,
+    diff::{Snapshot, TableDiff},
+    freq::Frequencies,
+    output::{FormatOptions, Formats},
+    repl::Repl,
+    serve::Server,
+    *,
+};
+
+/// Generate and query Wubi code tables.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn get_lines(read: &mut io::BufReader<fs::File>) -> impl Iterator<Item = String> {
-    read.lines().map(|line| line.unwrap())
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build the table and write the selected outputs
+    Build(BuildArgs),
+    /// Print the candidates of each code
+    Lookup {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Also print every longer code starting with each code
+        #[arg(long)]
+        prefix: bool,
+        #[arg(required = true, value_name = "CODE")]
+        codes: Vec<String>,
+    },
+    /// Print the codes of each character or phrase, marking the shortest
+    /// practical one with `*`
+    Reverse {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Also explain how the code of each phrase is derived
+        #[arg(long)]
+        explain: bool,
+        #[arg(required = true, value_name = "PHRASE")]
+        texts: Vec<String>,
+    },
+    /// Build the table once and answer queries read from stdin
+    Repl {
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Build the table once and answer line-oriented JSON requests on
+    /// stdin, or on a Unix socket
+    Serve {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Listen on this Unix socket instead of stdin/stdout
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Count the characters and phrases of the table in corpus files and
+    /// write them as a weight file for `--weights`
+    Freq {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Weight file to write, resolved against `--data-dir` like `--weights`
+        #[arg(short, long, default_value = "weights.txt")]
+        output: PathBuf,
+        /// UTF-8 text files
+        #[arg(required = true, value_name = "CORPUS")]
+        corpus: Vec<PathBuf>,
+    },
+    /// Report code collisions (重码) of the table
+    Analyze {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Number of worst codes listed per code length
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// File listing the common characters to check; by default the
+        /// `--common-count` heaviest characters, if the table has weights
+        #[arg(long)]
+        common: Option<PathBuf>,
+        #[arg(long, default_value_t = 3500)]
+        common_count: usize,
+    },
+    /// List the filter policies and everything they hide or move
+    Filters {
+        #[command(flatten)]
+        inputs: Inputs,
+    },
+    /// Compare the table with an older build: a data directory, loaded with
+    /// the same options, or a generated `wb_nc_table.txt`, along with the
+    /// `wb_nc_reverse_table.txt` next to it if any
+    Diff {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// Print the changes as JSON
+        #[arg(long)]
+        json: bool,
+        /// Data directory or generated table of the older build
+        #[arg(value_name = "OLD")]
+        old: PathBuf,
+    },
+    /// List the output formats
+    Formats,
+    /// Load all inputs and report every problem found
+    Check {
+        #[command(flatten)]
+        inputs: Inputs,
+    },
 }
 
-fn main() {
-    // env_logger::init();
+#[derive(Args, Clone, Debug)]
+struct Inputs {
+    /// Directory that relative input paths are resolved against
+    #[arg(short, long, default_value = ".")]
+    data_dir: PathBuf,
+    /// Simplified code files, in order; repeat the option for each file
+    #[arg(long, action = ArgAction::Append, default_values = ["simplified1.txt", "simplified2.txt", "simplified3.txt"])]
+    simplified: Vec<PathBuf>,
+    /// Full codes of single characters, in order; repeat the option for each
+    /// file
+    #[arg(long, action = ArgAction::Append, default_value = "CJK.txt")]
+    cjk: Vec<PathBuf>,
+    /// Phrase list, one phrase per line
+    #[arg(long, default_value = "phrases.txt")]
+    phrases: PathBuf,
+    /// Code of a multi-code character used for phrases: first, longest or shortest
+    #[arg(
+        long,
+        default_value = "first",
+        value_parser = PossibleValuesParser::new(CodeChoice::ALL.map(CodeChoice::key))
+            .map(|s| s.parse::<CodeChoice>().expect("Checked by clap")),
+    )]
+    code_choice: CodeChoice,
+    /// Unicode blocks to load: `all` or a comma-separated list such as `basic,ext-a`
+    #[arg(long, default_value = "all")]
+    blocks: BlockFilter,
+    /// Phrase rules, one per line such as `e2=p11+p12+p21+p22`, replacing
+    /// the standard ones
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    /// What to do with a phrase containing a character missing from the full
+    /// table: fail, skip it with a count at the end, or skip it and list it
+    #[arg(long, value_enum, default_value_t = MissingChars::Fail)]
+    missing_chars: MissingChars,
+    /// Weights (`phrase<TAB>weight` lines, as written by `freq`) of the
+    /// entries whose source line has no weight column
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// Filter policies: `default`, `keep-all` or a comma-separated list such
+    /// as `hide-before-simplified,hide-duplicate-codes`, see `wubi-table filters`
+    #[arg(long, default_value = "default")]
+    filters: Filters,
+    /// Single characters in the phrase list: `reject` them as errors, or
+    /// accept them as their `full` table entries
+    #[arg(
+        long,
+        default_value = "reject",
+        value_parser = PossibleValuesParser::new(SingleChars::ALL.map(SingleChars::key))
+            .map(|s| s.parse::<SingleChars>().expect("Checked by clap")),
+    )]
+    single_chars: SingleChars,
+}
 
-    let mut simplified = SimplifiedCodeTable::new();
-    for i in 1..=3 {
-        let file = format!("simplified{i}.txt");
-        println!("Loading simplified table from {}", file);
-        let mut file = io::BufReader::new(fs::File::open(file).unwrap());
-        for line in get_lines(&mut file) {
-            let (chars, code) = line.split_once('\t').unwrap();
-            let mut chars = chars.chars();
-            if let Some(ch) = chars.next() {
-                assert!(
-                    chars.next().is_none(),
-                    "Simplified code is for single character"
-                );
-                let code = code.try_into().unwrap();
-                simplified.insert(&code, ch).unwrap();
-            } else {
-                todo!()
-            }
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum MissingChars {
+    Fail,
+    Skip,
+    Report,
+}
+
+#[derive(Args, Debug)]
+struct BuildArgs {
+    #[command(flatten)]
+    inputs: Inputs,
+    /// Directory the outputs are written to
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+    /// Outputs to generate, see `wubi-table formats`
+    #[arg(long, value_delimiter = ',', value_parser = parse_format, default_values = ["plain", "ios", "reverse"])]
+    outputs: Vec<String>,
+    /// What to do when an output file already exists
+    #[arg(long, value_enum, default_value_t = IfExists::Overwrite)]
+    if_exists: IfExists,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum IfExists {
+    Overwrite,
+    Skip,
+    Fail,
+}
+
+impl Inputs {
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.data_dir.join(path)
+    }
+
+    fn options(&self) -> Result<LoadOptions, LoadError> {
+        let mut options = LoadOptions::default();
+        options.blocks = self.blocks.clone();
+        options.code_choice = self.code_choice;
+        options.single_chars = self.single_chars;
+        if let Some(weights) = &self.weights {
+            let weights = self.resolve(weights);
+            eprintln!("Loading weights from {}", weights.display());
+            options.weights = load_weights(&weights)?;
+        }
+        if let Some(rules) = &self.rules {
+            let rules = self.resolve(rules);
+            eprintln!("Loading phrase rules from {}", rules.display());
+            options.rules = load_rules(&rules)?;
         }
+        Ok(options)
     }
 
-    println!("Loading full table");
-    let mut full = FullCodeTable::new();
-    let mut cjk = io::BufReader::new(fs::File::open("CJK.txt").unwrap());
-    for line in get_lines(&mut cjk) {
-        let entry = parse_line_with_codepoint(line.as_str()).unwrap();
-        full.insert(entry);
+    fn load(&self, options: &LoadOptions) -> Result<Table, LoadError> {
+        if self.missing_chars == MissingChars::Fail {
+            return self.load_with(options, &mut Err);
+        }
+        let mut skipped = Vec::new();
+        let table = self.load_with(options, &mut |e| match e {
+            LoadError::Parse {
+                source: ParseError::MissingCharacter(ch),
+                text,
+                ..
+            } => {
+                skipped.push((text, ch));
+                Ok(())
+            }
+            e => Err(e),
+        })?;
+        if !skipped.is_empty() {
+            if self.missing_chars == MissingChars::Report {
+                for (phrase, ch) in &skipped {
+                    eprintln!("  {phrase}: {ch} not in full table");
+                }
+            }
+            eprintln!(
+                "Skipped {} phrase(s) with characters missing from the full table",
+                skipped.len()
+            );
+        }
+        Ok(table)
     }
-    drop(cjk);
-
-    println!("Loading phrases");
-    let mut phrases = io::BufReader::new(fs::File::open("phrases.txt").unwrap());
-    for phrase in get_lines(&mut phrases) {
-        let wubi_code = get_code_for_phrase(phrase.as_str(), |ch| {
-            let code = full.code(&ch.to_string()).unwrap();
-            // *code.last().unwrap()
-            *code
-        });
-        let entry = WubiEntry { phrase, wubi_code };
-        full.insert(entry);
+
+    fn load_with(
+        &self,
+        options: &LoadOptions,
+        on_error: &mut load::OnError,
+    ) -> Result<Table, LoadError> {
+        let mut simplified = SimplifiedCodeTable::new();
+        for file in &self.simplified {
+            let file = self.resolve(file);
+            eprintln!("Loading simplified table from {}", file.display());
+            load_simplified_with(&mut simplified, &file, options, on_error)
+                .or_else(&mut *on_error)?;
+        }
+
+        let mut full = FullCodeTable::new();
+        for file in &self.cjk {
+            let file = self.resolve(file);
+            eprintln!("Loading full table from {}", file.display());
+            load_full_with(&mut full, &file, options, on_error).or_else(&mut *on_error)?;
+        }
+
+        eprintln!("Loading phrases");
+        load_phrases_with(&mut full, &self.resolve(&self.phrases), options, on_error)
+            .or_else(&mut *on_error)?;
+
+        Ok(Table::new(simplified, full).with_filters(self.filters.clone()))
     }
-    drop(phrases);
+}
 
-    let table = Table::new(simplified, full);
+fn main() -> ExitCode {
+    // env_logger::init();
 
-    println!("Generating table");
-    let mut table_file = io::BufWriter::new(fs::File::create("wb_nc_table.txt").unwrap());
-    let mut ios_table_file = io::BufWriter::new(fs::File::create("wb_nc_ios_table.txt").unwrap());
-    let mut simplified_table = table.simplified_table();
-    let mut full_table = table.filtered_full_table();
-    loop {
-        match (simplified_table.next(), full_table.next()) {
-            (None, None) => break,
-            (None, Some((code, phrases))) => {
-                write!(table_file, "{code}").unwrap();
-                for phrase in phrases {
-                    write!(table_file, " {phrase}").unwrap();
-                    writeln!(ios_table_file, "{code}={phrase}").unwrap();
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Build(args) => build(&args)?,
+        Command::Lookup {
+            inputs,
+            prefix,
+            codes,
+        } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            for code in codes {
+                let code = WubiCode::try_from(code.as_str())?;
+                if prefix {
+                    for (code, candidates) in table.lookup_prefix(&code) {
+                        print_candidates(code, &candidates);
+                    }
+                } else {
+                    print_candidates(code, &table.lookup(&code));
                 }
-                writeln!(table_file).unwrap();
             }
-            (Some((code, ch)), None) => {
-                writeln!(table_file, "{code} {ch}").unwrap();
-                writeln!(ios_table_file, "{code}={ch}").unwrap();
-            }
-            (Some((simplified_code, simplified_ch)), Some((full_code, full_phrases))) => {
-                match simplified_code.cmp(&full_code) {
-                    Ordering::Less => {
-                        writeln!(table_file, "{simplified_code} {simplified_ch}").unwrap();
-                        writeln!(ios_table_file, "{simplified_code}={simplified_ch}").unwrap();
-                        write!(table_file, "{full_code}").unwrap();
-                        for phrase in full_phrases {
-                            write!(table_file, " {phrase}").unwrap();
-                            writeln!(ios_table_file, "{full_code}={phrase}").unwrap();
-                        }
-                        writeln!(table_file).unwrap();
-                    }
-                    Ordering::Equal => {
-                        write!(table_file, "{full_code} {simplified_ch}").unwrap();
-                        writeln!(ios_table_file, "{full_code}={simplified_ch}").unwrap();
-                        for phrase in full_phrases {
-                            write!(table_file, " {phrase}").unwrap();
-                            writeln!(ios_table_file, "{full_code}={phrase}").unwrap();
-                        }
-                        writeln!(table_file).unwrap();
+        }
+        Command::Reverse {
+            inputs,
+            explain,
+            texts,
+        } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            for phrase in texts {
+                print!("{phrase}");
+                for code in table.codes_of(&phrase) {
+                    print!(" {}", code.code);
+                    if code.practical {
+                        print!("*");
                     }
-                    Ordering::Greater => {
-                        write!(table_file, "{full_code}").unwrap();
-                        for phrase in full_phrases {
-                            write!(table_file, " {phrase}").unwrap();
-                            writeln!(ios_table_file, "{full_code}={phrase}").unwrap();
+                }
+                println!();
+                if explain && phrase.chars().nth(1).is_some() {
+                    match load::derive_phrase(table.full(), &phrase, &options) {
+                        Ok(derivation) => {
+                            for line in derivation.to_string().lines() {
+                                println!("  {line}");
+                            }
                         }
-                        writeln!(table_file).unwrap();
-                        writeln!(table_file, "{simplified_code} {simplified_ch}").unwrap();
-                        writeln!(ios_table_file, "{simplified_code}={simplified_ch}").unwrap();
+                        Err(e) => println!("  {e}"),
                     }
                 }
             }
         }
-    }
-    drop(table_file);
-    drop(ios_table_file);
-
-    println!("Generating reverse table");
-    let mut reverse_table_file =
-        io::BufWriter::new(fs::File::create("wb_nc_reverse_table.txt").unwrap());
-    let mut simplified_table = table.reverse_simplified_table();
-    let mut full_table = table.reverse_filtered_full_table();
-    loop {
-        match (simplified_table.next(), full_table.next()) {
-            (None, None) => break,
-            (None, Some((phrase, code))) => {
-                writeln!(reverse_table_file, "{phrase} {code}").unwrap();
+        Command::Repl { inputs } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            let stdin = io::stdin();
+            let prompt = stdin.is_terminal();
+            if prompt {
+                eprintln!("Type :help for help");
             }
-            (Some((ch, codes)), None) => {
-                write!(reverse_table_file, "{ch}").unwrap();
-                for code in codes {
-                    write!(reverse_table_file, " {code}").unwrap();
-                }
-                writeln!(reverse_table_file).unwrap();
+            Repl::new(&table, &options).run(stdin.lock(), &mut io::stdout(), prompt)?;
+        }
+        Command::Serve { inputs, socket } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            let server = Server::new(&table, &options);
+            match socket {
+                Some(path) => listen(&server, &path)?,
+                None => server.run(io::stdin().lock(), &mut io::stdout())?,
             }
-            (Some((simplified_ch, simplified_codes)), Some((full_phrase, full_code))) => {
-                match simplified_ch.to_string().cmp(&full_phrase) {
-                    Ordering::Less => {
-                        write!(reverse_table_file, "{simplified_ch}").unwrap();
-                        for code in simplified_codes {
-                            write!(reverse_table_file, " {code}").unwrap();
-                        }
-                        writeln!(reverse_table_file).unwrap();
-                        writeln!(reverse_table_file, "{full_phrase} {full_code}").unwrap();
-                    }
-                    Ordering::Equal => {
-                        write!(reverse_table_file, "{simplified_ch}").unwrap();
-                        for code in simplified_codes {
-                            write!(reverse_table_file, " {code}").unwrap();
-                        }
-                        writeln!(reverse_table_file, " {full_code}").unwrap();
-                    }
-                    Ordering::Greater => {
-                        writeln!(reverse_table_file, "{full_phrase} {full_code}").unwrap();
-                        write!(reverse_table_file, "{simplified_ch}").unwrap();
-                        for code in simplified_codes {
-                            write!(reverse_table_file, " {code}").unwrap();
-                        }
-                        writeln!(reverse_table_file).unwrap();
-                    }
+        }
+        Command::Freq {
+            inputs,
+            output,
+            corpus,
+        } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            let mut frequencies = Frequencies::new(table.full());
+            for file in corpus {
+                eprintln!("Counting {}", file.display());
+                for line in io::BufReader::new(fs::File::open(&file)?).lines() {
+                    frequencies.count(&line?);
                 }
             }
+            let output = inputs.resolve(&output);
+            frequencies.write(&mut io::BufWriter::new(fs::File::create(&output)?))?;
+            println!("Weights written to {}", output.display());
+        }
+        Command::Analyze {
+            inputs,
+            top,
+            common,
+            common_count,
+        } => {
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            let common: Vec<char> = match common {
+                Some(file) => fs::read_to_string(file)?
+                    .chars()
+                    .filter(|ch| !ch.is_whitespace())
+                    .collect(),
+                None => heaviest_chars(table.full(), common_count),
+            };
+            println!("{}", CollisionReport::new(&table, &common, top));
+        }
+        Command::Filters { inputs } => {
+            for filter in Filter::ALL {
+                println!("{:<24} {filter}", filter.key());
+            }
+            println!();
+            let options = inputs.options()?;
+            let table = inputs.load(&options)?;
+            println!("{:#}", FilterReport::new(&table));
+        }
+        Command::Diff { inputs, json, old } => {
+            let old = if old.is_dir() {
+                let old_inputs = Inputs {
+                    data_dir: old,
+                    ..inputs.clone()
+                };
+                Snapshot::new(&old_inputs.load(&old_inputs.options()?)?)
+            } else {
+                let formats = Formats::builtin();
+                let reverse = formats.get("reverse").expect("Built-in format");
+                let reverse = old.with_file_name(reverse.file_name());
+                eprintln!("Loading generated table from {}", old.display());
+                load_snapshot(&old, reverse.exists().then_some(reverse.as_path()))?
+            };
+            let new = Snapshot::new(&inputs.load(&inputs.options()?)?);
+            let diff = TableDiff::new(&old, &new);
+            if json {
+                println!("{}", diff.to_json());
+            } else {
+                println!("{diff}");
+            }
+        }
+        Command::Formats => {
+            for format in Formats::builtin().iter() {
+                println!(
+                    "{:<12} {:<24} {}",
+                    format.name(),
+                    format.file_name(),
+                    format.description()
+                );
+            }
+        }
+        Command::Check { inputs } => {
+            let mut diagnostics = Diagnostics::new();
+            inputs.load_with(&inputs.options()?, &mut |e| diagnostics.push(e))?;
+            if !diagnostics.is_empty() {
+                println!("{diagnostics}");
+                return Err(format!("{} problem(s) found", diagnostics.len()).into());
+            }
+            println!("OK");
+        }
+    }
+    Ok(())
+}
+
+/// The `count` characters of `full` with the highest weights.
+fn heaviest_chars(full: &FullCodeTable, count: usize) -> Vec<char> {
+    let mut chars: Vec<_> = full
+        .entries()
+        .filter_map(|(phrase, _)| {
+            let mut chars = phrase.chars();
+            match (chars.next(), chars.next(), full.weight(phrase)) {
+                (Some(ch), None, Some(weight)) => Some((weight, ch)),
+                _ => None,
+            }
+        })
+        .collect();
+    chars.sort_by_key(|&(weight, ch)| (std::cmp::Reverse(weight), ch));
+    chars.into_iter().take(count).map(|(_, ch)| ch).collect()
+}
+
+fn print_candidates(code: WubiCode, candidates: &[Candidate<'_>]) {
+    print!("{code}");
+    for candidate in candidates {
+        print!(" {candidate}");
+    }
+    println!();
+}
+
+/// Serves every connection to the Unix socket at `path` on its own thread.
+///
+/// A socket left at `path` by an earlier run is replaced, unless a server
+/// still accepts connections on it. The socket is removed again when serving
+/// stops, including on SIGINT or SIGTERM.
+#[cfg(unix)]
+fn listen(server: &Server<'_>, path: &Path) -> io::Result<()> {
+    use std::os::unix::{
+        fs::FileTypeExt as _,
+        net::{UnixListener, UnixStream},
+    };
+
+    /// Removes the socket file when dropped.
+    struct Unlink<'a>(&'a Path);
+
+    impl Drop for Unlink<'_> {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(self.0);
         }
     }
+
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a server is already listening on {}", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let _unlink = Unlink(path);
+    let socket = path.to_path_buf();
+    ctrlc::set_handler(move || {
+        let _ = fs::remove_file(&socket);
+        std::process::exit(130);
+    })
+    .map_err(io::Error::other)?;
+    eprintln!("Listening on {}", path.display());
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            scope.spawn(move || {
+                let input = io::BufReader::new(&stream);
+                let mut output = io::BufWriter::new(&stream);
+                if let Err(e) = server.run(input, &mut output) {
+                    eprintln!("connection closed: {e}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+#[cfg(not(unix))]
+fn listen(_: &Server<'_>, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    ))
+}
+
+/// Opens an output file, or returns `None` if it exists and should be skipped.
+fn create(args: &BuildArgs, file_name: &str) -> io::Result<Option<Box<dyn Write>>> {
+    let path = args.output_dir.join(file_name);
+    let file = match args.if_exists {
+        IfExists::Overwrite => fs::File::create(&path)?,
+        IfExists::Skip if path.exists() => {
+            println!("Skipping existing {}", path.display());
+            return Ok(None);
+        }
+        IfExists::Skip | IfExists::Fail => fs::File::create_new(&path)?,
+    };
+    Ok(Some(Box::new(io::BufWriter::new(file))))
+}
+
+fn parse_format(name: &str) -> Result<String, String> {
+    match Formats::builtin().get(name) {
+        Some(_) => Ok(name.to_string()),
+        None => Err("unknown format, see `wubi-table formats`".to_string()),
+    }
+}
+
+fn build(args: &BuildArgs) -> Result<(), Box<dyn Error>> {
+    let load_options = args.inputs.options()?;
+    let table = args.inputs.load(&load_options)?;
+    fs::create_dir_all(&args.output_dir)?;
+
+    let formats = Formats::builtin();
+    let mut options = FormatOptions::default();
+    options.rules = load_options.rules;
+    let mut writers = Vec::new();
+    for name in &args.outputs {
+        let format = formats.get(name).expect("Checked by clap");
+        if let Some(file) = create(args, format.file_name())? {
+            writers.push(format.writer(file, &options));
+        }
+    }
+
+    println!("Generating table");
+    output::write_table(&table, &mut writers)?;
+
+    println!("Filters");
+    for line in FilterReport::new(&table).to_string().lines() {
+        println!("  {line}");
+    }
+
+    println!("Characters per block");
+    for (block, count) in table.block_counts() {
+        println!("  {block}: {count}");
+    }
+    Ok(())
 }
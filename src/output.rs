@@ -0,0 +1,341 @@
+//! Output formats generated from a [`Table`].
+//!
+//! Every format implements [`TableWriter`] and is fed the merged records of
+//! the table by [`write_table`]. [`Formats`] maps format names to writers so
+//! that library users can register formats of their own.
+
+use crate::{
+    rule::PhraseRules,
+    table::{Candidate, Table, WubiCode},
+};
+use std::io::{self, Write};
+
+pub mod fcitx;
+pub mod ibus;
+pub mod plain;
+pub mod rime;
+
+/// An output format, fed by [`write_table`].
+///
+/// The forward records (code to candidates) are passed first, in code order,
+/// then the reverse records (phrase to codes), in phrase order. A format
+/// only overrides the methods of the records it needs.
+pub trait TableWriter {
+    /// Called once before any record, e.g. to write a header.
+    fn begin(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// `weights` holds the weight of each candidate, decreasing along them
+    /// (see [`Table::candidate_weights`]).
+    fn write_code(
+        &mut self,
+        _code: WubiCode,
+        _candidates: &[Candidate<'_>],
+        _weights: &[u32],
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_phrase(&mut self, _phrase: &str, _codes: &[WubiCode]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once after all records; should flush the output.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Feeds the records of `table` to every writer in a single pass.
+pub fn write_table(table: &Table, writers: &mut [Box<dyn TableWriter>]) -> io::Result<()> {
+    for writer in writers.iter_mut() {
+        writer.begin()?;
+    }
+    for (code, candidates) in table.candidates() {
+        let weights = table.candidate_weights(&candidates);
+        for writer in writers.iter_mut() {
+            writer.write_code(code, &candidates, &weights)?;
+        }
+    }
+    for (phrase, codes) in table.reverse_entries() {
+        for writer in writers.iter_mut() {
+            writer.write_phrase(&phrase, &codes)?;
+        }
+    }
+    for writer in writers.iter_mut() {
+        writer.finish()?;
+    }
+    Ok(())
+}
+
+/// Settings shared by the built-in formats.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct FormatOptions {
+    /// Name of the generated dictionary or schema.
+    pub name: String,
+    pub version: String,
+    /// Phrase rules declared by formats that let the input method build phrases.
+    pub rules: PhraseRules,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            name: "wubi_nc".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            rules: PhraseRules::standard(),
+        }
+    }
+}
+
+type MakeWriter = dyn Fn(Box<dyn Write>, &FormatOptions) -> Box<dyn TableWriter>;
+
+/// A named output format and the file it is written to by default.
+pub struct Format {
+    name: String,
+    file_name: String,
+    description: String,
+    make: Box<MakeWriter>,
+}
+
+impl Format {
+    pub fn new(
+        name: impl Into<String>,
+        file_name: impl Into<String>,
+        description: impl Into<String>,
+        make: impl Fn(Box<dyn Write>, &FormatOptions) -> Box<dyn TableWriter> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            file_name: file_name.into(),
+            description: description.into(),
+            make: Box::new(make),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Creates a writer of this format over `w`.
+    pub fn writer(&self, w: Box<dyn Write>, options: &FormatOptions) -> Box<dyn TableWriter> {
+        (self.make)(w, options)
+    }
+}
+
+/// Registry of output formats, looked up by name.
+pub struct Formats {
+    formats: Vec<Format>,
+}
+
+impl Formats {
+    pub fn new() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    /// The formats shipped with the crate.
+    pub fn builtin() -> Self {
+        let mut formats = Self::new();
+        formats.register(Format::new(
+            "plain",
+            "wb_nc_table.txt",
+            "code followed by its candidates",
+            |w, _| Box::new(plain::PlainWriter::new(w)),
+        ));
+        formats.register(Format::new(
+            "ios",
+            "wb_nc_ios_table.txt",
+            "one `code=phrase` per line",
+            |w, _| Box::new(plain::IosWriter::new(w)),
+        ));
+        formats.register(Format::new(
+            "reverse",
+            "wb_nc_reverse_table.txt",
+            "phrase followed by its codes",
+            |w, _| Box::new(plain::ReverseWriter::new(w)),
+        ));
+        formats.register(Format::new(
+            "rime",
+            "wubi_nc.dict.yaml",
+            "Rime dictionary",
+            |w, options| Box::new(rime::DictWriter::new(w, options)),
+        ));
+        formats.register(Format::new(
+            "rime-schema",
+            "wubi_nc.schema.yaml",
+            "Rime schema using the dictionary",
+            |w, options| Box::new(rime::SchemaWriter::new(w, options)),
+        ));
+        formats.register(Format::new(
+            "fcitx",
+            "wb_nc_fcitx.txt",
+            "fcitx5 table source for `libime_tabledict`",
+            |w, options| Box::new(fcitx::FcitxWriter::new(w, options)),
+        ));
+        formats.register(Format::new(
+            "ibus",
+            "wb_nc_ibus.txt",
+            "ibus-table source for `ibus-table-createdb`",
+            |w, options| Box::new(ibus::IbusWriter::new(w, options)),
+        ));
+        formats
+    }
+
+    /// Adds `format`, replacing any format of the same name.
+    pub fn register(&mut self, format: Format) {
+        match self.formats.iter_mut().find(|f| f.name == format.name) {
+            Some(existing) => *existing = format,
+            None => self.formats.push(format),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Format> {
+        self.formats.iter().find(|format| format.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Format> {
+        self.formats.iter()
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Setup shared by the unit tests of the formats.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A writer whose bytes stay readable after it is handed over.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Output of the built-in format `name` for `table`, with default options.
+    pub(crate) fn render(table: &Table, name: &str) -> String {
+        render_with(table, name, &FormatOptions::default())
+    }
+
+    /// Output of the built-in format `name` for `table`.
+    pub(crate) fn render_with(table: &Table, name: &str, options: &FormatOptions) -> String {
+        let buffer = Buffer::default();
+        let format = Formats::builtin()
+            .get(name)
+            .unwrap()
+            .writer(Box::new(buffer.clone()), options);
+        write_table(table, &mut [format]).unwrap();
+        String::from_utf8(buffer.0.take()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::table;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Records every call it receives.
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl TableWriter for Recorder {
+        fn begin(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().push("begin".to_string());
+            Ok(())
+        }
+
+        fn write_code(
+            &mut self,
+            code: WubiCode,
+            candidates: &[Candidate<'_>],
+            weights: &[u32],
+        ) -> io::Result<()> {
+            let candidates: Vec<_> = candidates.iter().map(ToString::to_string).collect();
+            self.0
+                .borrow_mut()
+                .push(format!("{code} {candidates:?} {weights:?}"));
+            Ok(())
+        }
+
+        fn write_phrase(&mut self, phrase: &str, codes: &[WubiCode]) -> io::Result<()> {
+            let codes: Vec<_> = codes.iter().map(ToString::to_string).collect();
+            self.0.borrow_mut().push(format!("{phrase} {codes:?}"));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().push("finish".to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_writer() {
+        let table = table(&[("k", '中')], &[("中", "khk"), ("中国", "khlg")]);
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut formats = Formats::new();
+        let recorded = calls.clone();
+        formats.register(Format::new("calls", "calls.txt", "", move |_, _| {
+            Box::new(Recorder(recorded.clone()))
+        }));
+        let writer = formats
+            .get("calls")
+            .unwrap()
+            .writer(Box::new(io::sink()), &FormatOptions::default());
+        write_table(&table, &mut [writer]).unwrap();
+        assert_eq!(
+            calls.take(),
+            [
+                "begin",
+                "k [\"中\"] [1]",
+                "khk [\"中\"] [1]",
+                "khlg [\"中国\"] [1]",
+                "中 [\"k\", \"khk\"]",
+                "中国 [\"khlg\"]",
+                "finish",
+            ]
+        );
+    }
+
+    #[test]
+    fn register_replaces_by_name() {
+        let mut formats = Formats::builtin();
+        let count = formats.iter().count();
+        formats.register(Format::new("plain", "plain.txt", "replaced", |w, _| {
+            Box::new(plain::ReverseWriter::new(w))
+        }));
+        formats.register(Format::new("extra", "extra.txt", "added", |w, _| {
+            Box::new(plain::PlainWriter::new(w))
+        }));
+        assert_eq!(formats.iter().count(), count + 1);
+        let plain = formats.get("plain").unwrap();
+        assert_eq!(
+            (plain.file_name(), plain.description()),
+            ("plain.txt", "replaced")
+        );
+        assert_eq!(formats.iter().next().unwrap().name(), "plain");
+        assert_eq!(formats.iter().last().unwrap().name(), "extra");
+    }
+}
@@ -0,0 +1,83 @@
+//! Text source of a fcitx5 table dictionary, as read by `libime_tabledict`.
+
+use super::{FormatOptions, TableWriter};
+use crate::{
+    rule::PhraseRules,
+    table::{Candidate, WubiCode},
+};
+use std::io::{self, Write};
+
+/// Writes the `KeyCode=`/`Length=` header, the `[Rule]` section derived from
+/// the phrase rules and the `[Data]` section with one `code candidate` per
+/// line.
+pub struct FcitxWriter<W> {
+    w: W,
+    rules: PhraseRules,
+}
+
+impl<W: Write> FcitxWriter<W> {
+    pub fn new(w: W, options: &FormatOptions) -> Self {
+        Self {
+            w,
+            rules: options.rules.clone(),
+        }
+    }
+}
+
+impl<W: Write> TableWriter for FcitxWriter<W> {
+    fn begin(&mut self) -> io::Result<()> {
+        writeln!(self.w, "KeyCode={}", WubiCode::ALPHABET)?;
+        writeln!(self.w, "Length={}", WubiCode::MAX_KEYS)?;
+        writeln!(self.w, "[Rule]")?;
+        for rule in self.rules.rules() {
+            writeln!(self.w, "{rule}")?;
+        }
+        writeln!(self.w, "[Data]")
+    }
+
+    fn write_code(
+        &mut self,
+        code: WubiCode,
+        candidates: &[Candidate<'_>],
+        _weights: &[u32],
+    ) -> io::Result<()> {
+        for candidate in candidates {
+            writeln!(self.w, "{code} {candidate}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        output::{
+            FormatOptions,
+            testing::{render, render_with},
+        },
+        table::testing::table,
+    };
+
+    #[test]
+    fn rules_follow_options() {
+        let table = table(&[("k", '中')], &[("中", "khk"), ("中国", "khlg")]);
+        assert_eq!(
+            render(&table, "fcitx"),
+            "KeyCode=abcdefghijklmnopqrstuvwxy\nLength=4\n\
+             [Rule]\ne2=p11+p12+p21+p22\ne3=p11+p21+p31+p32\na4=p11+p21+p31+n11\n\
+             [Data]\nk 中\nkhk 中\nkhlg 中国\n"
+        );
+
+        let options = FormatOptions {
+            rules: "e2=p11+p12+p21+p22\na3=p11+p21+p31+n11".parse().unwrap(),
+            ..FormatOptions::default()
+        };
+        let source = render_with(&table, "fcitx", &options);
+        let (rules, _) = source.split_once("[Data]\n").unwrap();
+        assert!(rules.ends_with("[Rule]\ne2=p11+p12+p21+p22\na3=p11+p21+p31+n11\n"));
+    }
+}
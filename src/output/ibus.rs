@@ -0,0 +1,114 @@
+//! ibus-table source (`.txt` read by `ibus-table-createdb`).
+
+use super::{FormatOptions, TableWriter};
+use crate::{
+    rule::PhraseRules,
+    table::{Candidate, WubiCode},
+};
+use itertools::Itertools as _;
+use std::io::{self, Write};
+
+/// Writes the definition block followed by `code<TAB>phrase<TAB>freq` rows.
+///
+/// Frequencies decrease along the candidate order of each code, so the
+/// simplified character is listed first.
+pub struct IbusWriter<W> {
+    w: W,
+    name: String,
+    rules: PhraseRules,
+}
+
+impl<W: Write> IbusWriter<W> {
+    pub fn new(w: W, options: &FormatOptions) -> Self {
+        Self {
+            w,
+            name: options.name.clone(),
+            rules: options.rules.clone(),
+        }
+    }
+}
+
+impl<W: Write> TableWriter for IbusWriter<W> {
+    fn begin(&mut self) -> io::Result<()> {
+        let w = &mut self.w;
+        let name = &self.name;
+        writeln!(w, "### ibus-table source generated by wubi-table")?;
+        writeln!(w, "BEGIN_DEFINITION")?;
+        writeln!(w, "SCHEMA = {name}")?;
+        writeln!(w, "NAME = {name}")?;
+        writeln!(w, "NAME.zh_CN = 五笔")?;
+        writeln!(w, "LANGUAGES = zh_CN")?;
+        writeln!(w, "SYMBOL = 五")?;
+        writeln!(w, "STATUS_PROMPT = 五")?;
+        writeln!(w, "VALID_INPUT_CHARS = {}", WubiCode::ALPHABET)?;
+        writeln!(w, "MAX_KEY_LENGTH = {}", WubiCode::MAX_KEYS)?;
+        writeln!(w, "LAYOUT = default")?;
+        writeln!(w, "AUTO_COMMIT = FALSE")?;
+        writeln!(w, "AUTO_SELECT = FALSE")?;
+        writeln!(w, "USER_CAN_DEFINE_PHRASE = TRUE")?;
+        writeln!(
+            w,
+            "RULES = {}",
+            self.rules
+                .rules()
+                .iter()
+                .map(|rule| rule.ibus_notation())
+                .join(";")
+        )?;
+        writeln!(w, "END_DEFINITION")?;
+        writeln!(w)?;
+        writeln!(w, "BEGIN_TABLE")
+    }
+
+    fn write_code(
+        &mut self,
+        code: WubiCode,
+        candidates: &[Candidate<'_>],
+        weights: &[u32],
+    ) -> io::Result<()> {
+        for (candidate, weight) in candidates.iter().zip(weights) {
+            writeln!(self.w, "{code}\t{candidate}\t{weight}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        writeln!(self.w, "END_TABLE")?;
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        WubiEntry,
+        output::testing::render,
+        table::{
+            SimplifiedCodeTable, Table,
+            testing::{code, full},
+        },
+    };
+
+    #[test]
+    fn frequencies_decrease_along_candidates() {
+        let mut simplified = SimplifiedCodeTable::new();
+        simplified.insert(&code("k"), '中').unwrap();
+        let mut full = full(&[("中", "khk"), ("贵国", "khlg")]);
+        for phrase in ["中国", "跨国"] {
+            full.insert(WubiEntry::new(phrase.into(), code("khlg")).with_weight(Some(5)))
+                .unwrap();
+        }
+        let source = render(&Table::new(simplified, full), "ibus");
+        let (definition, table) = source.split_once("BEGIN_TABLE\n").unwrap();
+        assert!(definition.starts_with("### ibus-table source generated by wubi-table\n"));
+        assert!(
+            definition
+                .contains("RULES = ce2:p11+p12+p21+p22;ce3:p11+p21+p31+p32;ca4:p11+p21+p31+n11\n")
+        );
+        assert!(definition.ends_with("END_DEFINITION\n\n"));
+        assert_eq!(
+            table,
+            "k\t中\t1\nkhk\t中\t1\nkhlg\t中国\t6\nkhlg\t跨国\t5\nkhlg\t贵国\t1\nEND_TABLE\n"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! The plain-text tables: forward, iOS text replacement and reverse.
+
+use super::TableWriter;
+use crate::table::{Candidate, WubiCode};
+use std::io::{self, Write};
+
+/// `wb_nc_table.txt`: each code followed by its candidates.
+pub struct PlainWriter<W> {
+    w: W,
+}
+
+impl<W: Write> PlainWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+}
+
+impl<W: Write> TableWriter for PlainWriter<W> {
+    fn write_code(
+        &mut self,
+        code: WubiCode,
+        candidates: &[Candidate<'_>],
+        _weights: &[u32],
+    ) -> io::Result<()> {
+        write!(self.w, "{code}")?;
+        for candidate in candidates {
+            write!(self.w, " {candidate}")?;
+        }
+        writeln!(self.w)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// `wb_nc_ios_table.txt`: one `code=phrase` per line.
+pub struct IosWriter<W> {
+    w: W,
+}
+
+impl<W: Write> IosWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+}
+
+impl<W: Write> TableWriter for IosWriter<W> {
+    fn write_code(
+        &mut self,
+        code: WubiCode,
+        candidates: &[Candidate<'_>],
+        _weights: &[u32],
+    ) -> io::Result<()> {
+        for candidate in candidates {
+            writeln!(self.w, "{code}={candidate}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// `wb_nc_reverse_table.txt`: each phrase followed by its codes.
+pub struct ReverseWriter<W> {
+    w: W,
+}
+
+impl<W: Write> ReverseWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+}
+
+impl<W: Write> TableWriter for ReverseWriter<W> {
+    fn write_phrase(&mut self, phrase: &str, codes: &[WubiCode]) -> io::Result<()> {
+        write!(self.w, "{phrase}")?;
+        for code in codes {
+            write!(self.w, " {code}")?;
+        }
+        writeln!(self.w)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::testing::render, table::testing::table};
+
+    #[test]
+    fn plain_tables() {
+        let table = table(
+            &[("x", '经'), ("xca", '经')],
+            &[
+                ("经", "xca"),
+                ("经", "xcag"),
+                ("纟", "xxx"),
+                ("纪", "xca"),
+                ("经济", "xciy"),
+            ],
+        );
+        assert_eq!(
+            render(&table, "plain"),
+            "x 经\nxca 经 纪\nxcag 经\nxciy 经济\nxxx 纟\n"
+        );
+        assert_eq!(
+            render(&table, "ios"),
+            "x=经\nxca=经\nxca=纪\nxcag=经\nxciy=经济\nxxx=纟\n"
+        );
+        assert_eq!(
+            render(&table, "reverse"),
+            "纟 xxx\n纪 xca\n经 x xca xcag\n经济 xciy\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Rime `*.dict.yaml` dictionary and `*.schema.yaml` schema.
+
+use super::{FormatOptions, TableWriter};
+use crate::table::{Candidate, WubiCode};
+use std::io::{self, Write};
+
+/// Writes a `<name>.dict.yaml` with `text<TAB>code<TAB>weight` rows.
+///
+/// Rows keep the table's candidate order, and the weights decrease along it,
+/// so the simplified character ranks first under its code.
+pub struct DictWriter<W> {
+    w: W,
+    name: String,
+    version: String,
+}
+
+impl<W: Write> DictWriter<W> {
+    pub fn new(w: W, options: &FormatOptions) -> Self {
+        Self {
+            w,
+            name: options.name.clone(),
+            version: options.version.clone(),
+        }
+    }
+}
+
+impl<W: Write> TableWriter for DictWriter<W> {
+    fn begin(&mut self) -> io::Result<()> {
+        let w = &mut self.w;
+        writeln!(w, "# Rime dictionary")?;
+        writeln!(w, "# encoding: utf-8")?;
+        writeln!(w)?;
+        writeln!(w, "---")?;
+        writeln!(w, "name: {}", self.name)?;
+        writeln!(w, "version: \"{}\"", self.version)?;
+        writeln!(w, "sort: original")?;
+        writeln!(w, "columns:")?;
+        writeln!(w, "  - text")?;
+        writeln!(w, "  - code")?;
+        writeln!(w, "  - weight")?;
+        writeln!(w, "...")?;
+        writeln!(w)
+    }
+
+    fn write_code(
+        &mut self,
+        code: WubiCode,
+        candidates: &[Candidate<'_>],
+        weights: &[u32],
+    ) -> io::Result<()> {
+        for (candidate, weight) in candidates.iter().zip(weights) {
+            writeln!(self.w, "{candidate}\t{code}\t{weight}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// Writes a `<name>.schema.yaml` skeleton using the dictionary `<name>`.
+pub struct SchemaWriter<W> {
+    w: W,
+    name: String,
+    version: String,
+}
+
+impl<W: Write> SchemaWriter<W> {
+    pub fn new(w: W, options: &FormatOptions) -> Self {
+        Self {
+            w,
+            name: options.name.clone(),
+            version: options.version.clone(),
+        }
+    }
+}
+
+impl<W: Write> TableWriter for SchemaWriter<W> {
+    fn begin(&mut self) -> io::Result<()> {
+        write!(
+            self.w,
+            r#"# Rime schema
+# encoding: utf-8
+
+schema:
+  schema_id: {name}
+  name: {name}
+  version: "{version}"
+  description: |
+    Generated by wubi-table.
+
+switches:
+  - name: ascii_mode
+    reset: 0
+    states: [ 中文, 西文 ]
+  - name: full_shape
+    states: [ 半角, 全角 ]
+
+engine:
+  processors:
+    - ascii_composer
+    - recognizer
+    - key_binder
+    - speller
+    - punctuator
+    - selector
+    - navigator
+    - express_editor
+  segmentors:
+    - ascii_segmentor
+    - matcher
+    - abc_segmentor
+    - punct_segmentor
+    - fallback_segmentor
+  translators:
+    - punct_translator
+    - table_translator
+
+speller:
+  alphabet: {alphabet}
+  delimiter: " ;'"
+  max_code_length: {max_code_length}
+  auto_select: true
+
+translator:
+  dictionary: {name}
+  enable_charset_filter: false
+  enable_sentence: false
+  enable_encoder: false
+  encode_commit_history: false
+  enable_user_dict: false
+
+punctuator:
+  import_preset: default
+
+key_binder:
+  import_preset: default
+
+recognizer:
+  import_preset: default
+"#,
+            name = self.name,
+            version = self.version,
+            alphabet = WubiCode::ALPHABET,
+            max_code_length = WubiCode::MAX_KEYS,
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        WubiEntry,
+        output::testing::render,
+        table::{
+            SimplifiedCodeTable, Table,
+            testing::{code, full, table},
+        },
+    };
+
+    #[test]
+    fn weighted_dict_rows() {
+        let mut simplified = SimplifiedCodeTable::new();
+        simplified.insert(&code("k"), '中').unwrap();
+        let mut full = full(&[("国", "lgyi"), ("跨国", "khlg")]);
+        for (phrase, wubi_code, weight) in [("中", "khk", 30), ("中国", "khlg", 8)] {
+            full.insert(WubiEntry::new(phrase.into(), code(wubi_code)).with_weight(Some(weight)))
+                .unwrap();
+        }
+        let dict = render(&Table::new(simplified, full), "rime");
+        let (header, rows) = dict.split_once("...\n\n").unwrap();
+        assert!(header.starts_with("# Rime dictionary\n"));
+        assert!(header.contains("name: wubi_nc\nversion: "));
+        assert!(header.contains("sort: original\ncolumns:\n  - text\n  - code\n  - weight\n"));
+        assert_eq!(
+            rows,
+            "中\tk\t30\n中\tkhk\t30\n中国\tkhlg\t8\n跨国\tkhlg\t1\n国\tlgyi\t1\n"
+        );
+    }
+
+    #[test]
+    fn schema_uses_dict() {
+        let schema = render(&table(&[], &[]), "rime-schema");
+        assert!(schema.contains("  schema_id: wubi_nc\n"));
+        assert!(schema.contains("  dictionary: wubi_nc\n"));
+        assert!(schema.contains("  alphabet: abcdefghijklmnopqrstuvwxy\n"));
+        assert!(schema.contains("  max_code_length: 4\n"));
+    }
+}
@@ -0,0 +1,191 @@
+//! Interactive queries against a built [`Table`].
+
+use crate::{
+    load::{LoadOptions, derive_phrase},
+    table::{Table, WubiCode},
+};
+use itertools::Itertools as _;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+<code>            candidates of a code of a-y letters
+<text>            codes of a character or phrase, and how a phrase is encoded
+:prefix <code>    candidates of every code starting with <code>
+:stats            size of the table
+:conflicts [n]    the n codes with the most candidates (default 10)
+:help             this message
+:quit             leave";
+
+/// Answers one query per line.
+pub struct Repl<'a> {
+    table: &'a Table,
+    options: &'a LoadOptions,
+}
+
+impl<'a> Repl<'a> {
+    /// `options` drive the phrase encoding shown; they should be the ones the
+    /// table was loaded with.
+    pub fn new(table: &'a Table, options: &'a LoadOptions) -> Self {
+        Self { table, options }
+    }
+
+    /// Reads queries from `input` until it ends or `:quit`, writing a `> `
+    /// prompt before each if `prompt` is set.
+    pub fn run(&self, input: impl BufRead, out: &mut impl Write, prompt: bool) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            if !self.eval(line?.trim(), out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Answers a single query; returns `false` if it asks to quit.
+    pub fn eval(&self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            ":quit" | ":q" => return Ok(false),
+            ":help" | ":h" => writeln!(out, "{HELP}")?,
+            ":prefix" => match WubiCode::try_from(arg) {
+                Ok(prefix) => {
+                    for (code, candidates) in self.table.lookup_prefix(&prefix) {
+                        writeln!(out, "{code} {}", candidates.iter().join(" "))?;
+                    }
+                }
+                Err(e) => writeln!(out, "invalid code {arg:?}: {e}")?,
+            },
+            ":stats" => self.stats(out)?,
+            ":conflicts" => match arg {
+                "" => self.conflicts(10, out)?,
+                n => match n.parse() {
+                    Ok(n) => self.conflicts(n, out)?,
+                    Err(e) => writeln!(out, "invalid count {n:?}: {e}")?,
+                },
+            },
+            _ if command.starts_with(':') => {
+                writeln!(out, "unknown command {command}, try :help")?;
+            }
+            _ if line.bytes().all(|b| b.is_ascii_lowercase()) => match WubiCode::try_from(line) {
+                Ok(code) => writeln!(out, "{}", self.table.lookup(&code).iter().join(" "))?,
+                Err(e) => writeln!(out, "invalid code {line:?}: {e}")?,
+            },
+            _ => self.text(line, out)?,
+        }
+        Ok(true)
+    }
+
+    fn text(&self, text: &str, out: &mut impl Write) -> io::Result<()> {
+        let codes = self.table.codes_of(text);
+        if codes.is_empty() {
+            writeln!(out, "{text}: not in table")?;
+        } else {
+            write!(out, "{text}")?;
+            for code in codes {
+                write!(
+                    out,
+                    " {}{}",
+                    code.code,
+                    if code.practical { "*" } else { "" }
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        if text.chars().count() < 2 {
+            return Ok(());
+        }
+        match derive_phrase(self.table.full(), text, self.options) {
+            Ok(derivation) => {
+                for line in derivation.to_string().lines() {
+                    writeln!(out, "  {line}")?;
+                }
+            }
+            Err(e) => writeln!(out, "  {e}")?,
+        }
+        Ok(())
+    }
+
+    fn stats(&self, out: &mut impl Write) -> io::Result<()> {
+        let (codes, entries) = self
+            .table
+            .candidates()
+            .fold((0, 0), |(codes, entries), (_, candidates)| {
+                (codes + 1, entries + candidates.len())
+            });
+        writeln!(out, "codes: {codes}")?;
+        writeln!(out, "entries: {entries}")?;
+        let simplified = self
+            .table
+            .simplified_table()
+            .counts_by(|(code, _)| code.key_count());
+        for level in 1..WubiCode::MAX_KEYS {
+            let count = simplified.get(&level).copied().unwrap_or_default();
+            writeln!(out, "level {level} simplified codes: {count}")?;
+        }
+        let blocks = self.table.block_counts();
+        writeln!(out, "characters: {}", blocks.values().sum::<usize>())?;
+        for (block, count) in blocks {
+            writeln!(out, "  {block}: {count}")?;
+        }
+        Ok(())
+    }
+
+    fn conflicts(&self, n: usize, out: &mut impl Write) -> io::Result<()> {
+        let conflicts: Vec<_> = self
+            .table
+            .candidates()
+            .filter(|(_, candidates)| candidates.len() > 1)
+            .collect();
+        writeln!(out, "codes with several candidates: {}", conflicts.len())?;
+        let worst = conflicts
+            .iter()
+            .sorted_by_key(|(code, candidates)| (std::cmp::Reverse(candidates.len()), *code))
+            .take(n);
+        for (code, candidates) in worst {
+            writeln!(
+                out,
+                "{code} ({}) {}",
+                candidates.len(),
+                candidates.iter().join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::table;
+
+    #[test]
+    fn eval_queries() {
+        let table = table(
+            &[("k", '中')],
+            &[("中", "khk"), ("国", "lgyi"), ("中国", "khlg")],
+        );
+        let options = LoadOptions::default();
+        let repl = Repl::new(&table, &options);
+
+        let mut out = Vec::new();
+        assert!(repl.eval("khlg", &mut out).unwrap());
+        assert!(repl.eval("中国", &mut out).unwrap());
+        assert!(repl.eval(":prefix kh", &mut out).unwrap());
+        assert!(!repl.eval(":quit", &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "中国\n中国 khlg*\n  rule for 2 characters: e2=p11+p12+p21+p22\n  \
+             中 khk: letters 1,2 -> kh\n  国 lgyi: letters 1,2 -> lg\n  = khlg\n\
+             khk 中\nkhlg 中国\n"
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Rules deriving the code of a phrase from the codes of its characters.
+
+use crate::{ParseError, table::WubiCode};
+use itertools::Itertools as _;
+use std::{fmt, str::FromStr};
+
+/// Which character of a phrase a key is taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharPosition {
+    /// The n-th character from the start, counting from 0.
+    Start(usize),
+    /// The n-th character from the end, counting from 0.
+    End(usize),
+}
+
+impl CharPosition {
+    /// Index of the character in a phrase of `len` characters.
+    pub fn resolve(self, len: usize) -> Option<usize> {
+        match self {
+            CharPosition::Start(n) => (n < len).then_some(n),
+            CharPosition::End(n) => len.checked_sub(n + 1),
+        }
+    }
+}
+
+/// One key of a phrase code: the `key`-th letter (from 0) of a character's code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuleKey {
+    pub char: CharPosition,
+    pub key: usize,
+}
+
+impl RuleKey {
+    pub const fn new(char: CharPosition, key: usize) -> Self {
+        Self { char, key }
+    }
+}
+
+/// Parses one key: `p11` style (`p`/`n`, 1-based character and letter
+/// numbers), or, without the leading `c`, the `a1` of the compact `ca1` style
+/// where `a`..`m` count characters from the start and `z`..`n` from the end.
+fn parse_key(s: &str) -> Result<RuleKey, ParseError> {
+    let &[kind, char, key] = s.as_bytes() else {
+        return Err(ParseError::Invalid);
+    };
+    let number = |digit: u8| match digit {
+        b'1'..=b'9' => Ok(usize::from(digit - b'1')),
+        _ => Err(ParseError::Invalid),
+    };
+    let char = match (kind, char) {
+        (b'p', _) => CharPosition::Start(number(char)?),
+        (b'n', _) => CharPosition::End(number(char)?),
+        (b'c', b'a'..=b'm') => CharPosition::Start(usize::from(char - b'a')),
+        (b'c', b'n'..=b'z') => CharPosition::End(usize::from(b'z' - char)),
+        _ => return Err(ParseError::Invalid),
+    };
+    Ok(RuleKey::new(char, number(key)?))
+}
+
+/// Rule for phrases of exactly `length` characters, or of at least `length`
+/// characters if `or_longer` is set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhraseRule {
+    pub length: usize,
+    pub or_longer: bool,
+    pub keys: Vec<RuleKey>,
+}
+
+impl PhraseRule {
+    pub fn applies_to(&self, len: usize) -> bool {
+        if self.or_longer {
+            len >= self.length
+        } else {
+            len == self.length
+        }
+    }
+
+    /// `e` for an exact length, `a` for "at least".
+    fn kind(&self) -> char {
+        if self.or_longer { 'a' } else { 'e' }
+    }
+
+    /// Keys as `p11+p12+n11`: `p`/`n` counts characters from the start/end,
+    /// followed by the 1-based character and letter numbers.
+    fn keys_notation(&self) -> String {
+        self.keys
+            .iter()
+            .map(|key| match key.char {
+                CharPosition::Start(n) => format!("p{}{}", n + 1, key.key + 1),
+                CharPosition::End(n) => format!("n{}{}", n + 1, key.key + 1),
+            })
+            .join("+")
+    }
+
+    /// Which phrases the rule is for, e.g. `2 characters` or `4+ characters`.
+    pub fn description(&self) -> String {
+        let plus = if self.or_longer { "+" } else { "" };
+        format!("{}{plus} characters", self.length)
+    }
+
+    /// The rule in the ibus-table notation, e.g. `ce2:p11+p12+p21+p22`.
+    pub fn ibus_notation(&self) -> String {
+        format!("c{}{}:{}", self.kind(), self.length, self.keys_notation())
+    }
+}
+
+/// Writes the rule in the fcitx/libime notation, e.g. `e2=p11+p12+p21+p22`.
+impl fmt::Display for PhraseRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}={}", self.kind(), self.length, self.keys_notation())
+    }
+}
+
+/// Parses a rule in the fcitx (`e2=p11+p12+p21+p22`) or ibus
+/// (`ce2:p11+p12+p21+p22`) notation, or with the keys in the compact
+/// `e2=ca1ca2cb1cb2` style.
+impl FromStr for PhraseRule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (head, keys) = s.split_once(['=', ':']).ok_or(ParseError::Invalid)?;
+        let head = head.trim();
+        let head = head.strip_prefix('c').unwrap_or(head);
+        let or_longer = match head.chars().next() {
+            Some('e') => false,
+            Some('a') => true,
+            _ => return Err(ParseError::Invalid),
+        };
+        let length: usize = head[1..].parse()?;
+        let keys = keys.trim();
+        let keys: Vec<_> = if keys.contains('+') || keys.starts_with(['p', 'n']) {
+            keys.split('+').map(parse_key).try_collect()?
+        } else if keys.is_ascii() && keys.len().is_multiple_of(3) {
+            (0..keys.len())
+                .step_by(3)
+                .map(|i| parse_key(&keys[i..i + 3]))
+                .try_collect()?
+        } else {
+            return Err(ParseError::Invalid);
+        };
+        let valid = length > 0
+            && (1..=WubiCode::MAX_KEYS).contains(&keys.len())
+            && keys
+                .iter()
+                .all(|key| key.key < WubiCode::MAX_KEYS && key.char.resolve(length).is_some());
+        if !valid {
+            return Err(ParseError::Invalid);
+        }
+        Ok(Self {
+            length,
+            or_longer,
+            keys,
+        })
+    }
+}
+
+/// An ordered set of phrase rules; the first rule applying to a phrase wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhraseRules {
+    rules: Vec<PhraseRule>,
+}
+
+impl PhraseRules {
+    pub fn new(rules: Vec<PhraseRule>) -> Self {
+        Self { rules }
+    }
+
+    /// The usual 86-edition rules: two letters each of two-character phrases,
+    /// `1-1-2` letters of three-character phrases, and the first letter of the
+    /// first three and the last character of longer phrases.
+    pub fn standard() -> Self {
+        use CharPosition::{End, Start};
+        let key = RuleKey::new;
+        Self::new(vec![
+            PhraseRule {
+                length: 2,
+                or_longer: false,
+                keys: vec![
+                    key(Start(0), 0),
+                    key(Start(0), 1),
+                    key(Start(1), 0),
+                    key(Start(1), 1),
+                ],
+            },
+            PhraseRule {
+                length: 3,
+                or_longer: false,
+                keys: vec![
+                    key(Start(0), 0),
+                    key(Start(1), 0),
+                    key(Start(2), 0),
+                    key(Start(2), 1),
+                ],
+            },
+            PhraseRule {
+                length: 4,
+                or_longer: true,
+                keys: vec![
+                    key(Start(0), 0),
+                    key(Start(1), 0),
+                    key(Start(2), 0),
+                    key(End(0), 0),
+                ],
+            },
+        ])
+    }
+
+    pub fn rules(&self) -> &[PhraseRule] {
+        &self.rules
+    }
+
+    /// The rule used for phrases of `len` characters.
+    pub fn rule_for(&self, len: usize) -> Option<&PhraseRule> {
+        self.rules.iter().find(|rule| rule.applies_to(len))
+    }
+
+    /// Derives the code of `phrase` from the codes of its characters, as
+    /// returned by `char_code`.
+    ///
+    /// Fails with [`ParseError::NoRule`] if no rule applies to the phrase
+    /// length, [`ParseError::MissingCharacter`] if `char_code` has no code
+    /// for a character the rule takes letters from, or
+    /// [`ParseError::MissingLetter`] if it takes a letter past the end of that
+    /// code.
+    pub fn encode(
+        &self,
+        phrase: &str,
+        char_code: impl Fn(char) -> Option<WubiCode>,
+    ) -> Result<WubiCode, ParseError> {
+        self.derive(phrase, char_code)
+            .map(|derivation| derivation.code())
+    }
+
+    /// Like [`PhraseRules::encode`], recording which letters were taken from
+    /// which character.
+    pub fn derive(
+        &self,
+        phrase: &str,
+        char_code: impl Fn(char) -> Option<WubiCode>,
+    ) -> Result<PhraseDerivation<'_>, ParseError> {
+        let chars: Vec<char> = phrase.chars().collect();
+        let no_rule = || ParseError::NoRule(chars.len());
+        let rule = self.rule_for(chars.len()).ok_or_else(no_rule)?;
+        let mut sources: Vec<CharSource> = Vec::new();
+        let mut index = 0;
+        for (position, key) in rule.keys.iter().take(WubiCode::MAX_KEYS).enumerate() {
+            let char_index = key.char.resolve(chars.len()).ok_or_else(no_rule)?;
+            let source = match sources.iter().position(|s| s.index == char_index) {
+                Some(i) => &mut sources[i],
+                None => {
+                    let ch = chars[char_index];
+                    sources.push(CharSource {
+                        index: char_index,
+                        char: ch,
+                        code: char_code(ch).ok_or(ParseError::MissingCharacter(ch))?,
+                        keys: Vec::new(),
+                    });
+                    sources.last_mut().expect("Just pushed")
+                }
+            };
+            if key.key >= source.code.key_count() {
+                return Err(ParseError::MissingLetter(source.char, key.key + 1));
+            }
+            source.keys.push(key.key);
+            index += source.code.digit(key.key) * 26_u32.pow(3 - position as u32);
+        }
+        Ok(PhraseDerivation {
+            rule,
+            sources,
+            code: WubiCode::from_index(index),
+        })
+    }
+}
+
+/// A character contributing letters to a phrase code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CharSource {
+    /// Position of the character in the phrase, from 0.
+    pub index: usize,
+    pub char: char,
+    /// Full code of the character.
+    pub code: WubiCode,
+    /// Positions (from 0) of the letters taken from `code`, in the order
+    /// they appear in the phrase code.
+    pub keys: Vec<usize>,
+}
+
+/// How a phrase code was derived, as returned by [`PhraseRules::derive`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhraseDerivation<'a> {
+    rule: &'a PhraseRule,
+    sources: Vec<CharSource>,
+    code: WubiCode,
+}
+
+impl PhraseDerivation<'_> {
+    /// The rule applied.
+    pub fn rule(&self) -> &PhraseRule {
+        self.rule
+    }
+
+    /// Contributing characters, in the order their first letter is used.
+    pub fn sources(&self) -> &[CharSource] {
+        &self.sources
+    }
+
+    pub fn code(&self) -> WubiCode {
+        self.code
+    }
+}
+
+/// Writes one line for the rule, one per contributing character with the
+/// 1-based letter positions taken, and the resulting code, e.g.
+///
+/// ```text
+/// rule for 2 characters: e2=p11+p12+p21+p22
+/// 中 khk: letters 1,2 -> kh
+/// 国 lgyi: letters 1,2 -> lg
+/// = khlg
+/// ```
+impl fmt::Display for PhraseDerivation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rule for {}: {}", self.rule.description(), self.rule)?;
+        for source in &self.sources {
+            let code = source.code.to_string();
+            writeln!(
+                f,
+                "{} {code}: letters {} -> {}",
+                source.char,
+                source.keys.iter().map(|key| key + 1).join(","),
+                source
+                    .keys
+                    .iter()
+                    .filter_map(|&key| code.chars().nth(key))
+                    .collect::<String>()
+            )?;
+        }
+        write!(f, "= {}", self.code)
+    }
+}
+
+/// Parses one rule per line; blank lines and lines starting with `#` are
+/// ignored.
+impl FromStr for PhraseRules {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::parse)
+            .try_collect()
+            .map(Self::new)
+    }
+}
+
+impl Default for PhraseRules {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::code;
+
+    #[test]
+    fn standard_rules() {
+        let char_code = |ch| match ch {
+            '中' => Some(code("khk")),
+            '国' => Some(code("lgyi")),
+            '人' => Some(code("wwww")),
+            '民' => Some(code("nav")),
+            '华' => Some(code("wxf")),
+            _ => None,
+        };
+        let rules = PhraseRules::standard();
+        assert_eq!(rules.encode("中国", char_code), Ok(code("khlg")));
+        assert_eq!(rules.encode("中国人", char_code), Ok(code("klww")));
+        assert_eq!(rules.encode("中华人民", char_code), Ok(code("kwwn")));
+        assert_eq!(rules.encode("中华人民国", char_code), Ok(code("kwwl")));
+        assert_eq!(rules.encode("中", char_code), Err(ParseError::NoRule(1)));
+        assert_eq!(
+            rules.encode("中a国", char_code),
+            Err(ParseError::MissingCharacter('a'))
+        );
+        let past_end: PhraseRules = "e2=p11+p14+p21+p22".parse().unwrap();
+        assert_eq!(
+            past_end.encode("中国", char_code),
+            Err(ParseError::MissingLetter('中', 4))
+        );
+        assert_eq!(past_end.encode("国中", char_code), Ok(code("likh")));
+
+        let derivation = rules.derive("中华人民国", char_code).unwrap();
+        assert_eq!(derivation.rule().description(), "4+ characters");
+        let sources: Vec<_> = derivation
+            .sources()
+            .iter()
+            .map(|source| (source.index, source.char, source.keys.as_slice()))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                (0, '中', [0].as_slice()),
+                (1, '华', &[0]),
+                (2, '人', &[0]),
+                (4, '国', &[0])
+            ]
+        );
+        assert_eq!(
+            rules.derive("中国", char_code).unwrap().to_string(),
+            "rule for 2 characters: e2=p11+p12+p21+p22\n\
+             中 khk: letters 1,2 -> kh\n\
+             国 lgyi: letters 1,2 -> lg\n\
+             = khlg"
+        );
+
+        let rules: Vec<_> = rules.rules().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rules,
+            [
+                "e2=p11+p12+p21+p22",
+                "e3=p11+p21+p31+p32",
+                "a4=p11+p21+p31+n11"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_rules() {
+        let standard = PhraseRules::standard();
+        for text in [
+            "e2=p11+p12+p21+p22\ne3=p11+p21+p31+p32\na4=p11+p21+p31+n11",
+            "ce2:p11+p12+p21+p22\nce3:p11+p21+p31+p32\nca4:p11+p21+p31+n11",
+            "# compact\ne2=ca1ca2cb1cb2\n\ne3=ca1cb1cc1cc2\na4=ca1cb1cc1cz1\n",
+        ] {
+            assert_eq!(text.parse::<PhraseRules>(), Ok(standard.clone()));
+        }
+        for rule in standard.rules() {
+            assert_eq!(rule.to_string().parse(), Ok(rule.clone()));
+        }
+
+        let rule: PhraseRule = "a5=ca1cb1cy1cz1".parse().unwrap();
+        assert_eq!(rule.keys[2], RuleKey::new(CharPosition::End(1), 0));
+        for invalid in [
+            "e2",
+            "x2=p11",
+            "e2=p11+p31",
+            "e2=p15",
+            "e2=ca1ca2cb1cb2cb3",
+            "e0=p11",
+        ] {
+            assert!(invalid.parse::<PhraseRule>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Line-oriented JSON protocol answering lookups against a built [`Table`].
+//!
+//! Each input line holds one request object, or an array of them answered by
+//! an array of responses in the same order:
+//!
+//! ```text
+//! {"op":"lookup","code":"gg"}
+//! {"op":"lookup","code":"gg","prefix":true}
+//! {"op":"reverse","text":"中国"}
+//! [{"op":"encode_phrase","text":"中国"},{"op":"lookup","code":"khlg","id":2}]
+//! ```
+//!
+//! A response is `{"result":...}` or `{"error":{"kind":...,"message":...}}`,
+//! carrying the `id` of the request if it had one.
+
+use crate::{
+    ParseError,
+    load::{LoadOptions, encode_phrase},
+    strings,
+    table::{CodeKind, PhraseCode, Table, WubiCode},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, Write};
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+enum Request {
+    Lookup {
+        code: String,
+        #[serde(default)]
+        prefix: bool,
+    },
+    Reverse {
+        text: String,
+    },
+    EncodePhrase {
+        text: String,
+    },
+}
+
+/// Error object of a response.
+#[derive(Debug)]
+struct Failure {
+    kind: &'static str,
+    message: String,
+}
+
+impl From<ParseError> for Failure {
+    fn from(e: ParseError) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Failure {
+    fn from(e: serde_json::Error) -> Self {
+        Self {
+            kind: "invalid request",
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Answers JSON requests, one line at a time.
+pub struct Server<'a> {
+    table: &'a Table,
+    options: &'a LoadOptions,
+}
+
+impl<'a> Server<'a> {
+    /// `options` are used to encode phrases; they should be the ones the table
+    /// was loaded with.
+    pub fn new(table: &'a Table, options: &'a LoadOptions) -> Self {
+        Self { table, options }
+    }
+
+    /// Answers every line of `input` until it ends, flushing after each.
+    pub fn run(&self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            writeln!(out, "{}", self.answer(&line))?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Answers a single line holding a request or a batch of them.
+    pub fn answer(&self, line: &str) -> Value {
+        match serde_json::from_str(line) {
+            Ok(Value::Array(batch)) => batch.into_iter().map(|r| self.respond(r)).collect(),
+            Ok(request) => self.respond(request),
+            Err(e) => failure(None, e.into()),
+        }
+    }
+
+    fn respond(&self, mut request: Value) -> Value {
+        let id = request.get("id").cloned();
+        if let Value::Object(fields) = &mut request {
+            fields.remove("id");
+        }
+        let result = serde_json::from_value(request)
+            .map_err(Failure::from)
+            .and_then(|request| self.handle(request));
+        match result {
+            Ok(result) => with_id(id, json!({ "result": result })),
+            Err(e) => failure(id, e),
+        }
+    }
+
+    fn handle(&self, request: Request) -> Result<Value, Failure> {
+        match request {
+            Request::Lookup { code, prefix } => {
+                let code = WubiCode::try_from(code.as_str())?;
+                if prefix {
+                    let codes: Vec<_> = self
+                        .table
+                        .lookup_prefix(&code)
+                        .map(|(code, candidates)| {
+                            json!({ "code": code.to_string(), "candidates": strings(&candidates) })
+                        })
+                        .collect();
+                    Ok(json!({ "codes": codes }))
+                } else {
+                    let candidates = self.table.lookup(&code);
+                    Ok(json!({ "code": code.to_string(), "candidates": strings(&candidates) }))
+                }
+            }
+            Request::Reverse { text } => {
+                let codes: Vec<_> = self.table.codes_of(&text).iter().map(phrase_code).collect();
+                Ok(json!({ "text": text, "codes": codes }))
+            }
+            Request::EncodePhrase { text } => {
+                let code = encode_phrase(self.table.full(), &text, self.options)?;
+                Ok(json!({ "text": text, "code": code.to_string() }))
+            }
+        }
+    }
+}
+
+fn phrase_code(code: &PhraseCode) -> Value {
+    json!({
+        "code": code.code.to_string(),
+        "kind": match code.kind {
+            CodeKind::Simplified => "simplified",
+            CodeKind::Full => "full",
+        },
+        "rank": code.rank,
+        "practical": code.practical,
+    })
+}
+
+fn failure(id: Option<Value>, e: Failure) -> Value {
+    with_id(
+        id,
+        json!({ "error": { "kind": e.kind, "message": e.message } }),
+    )
+}
+
+fn with_id(id: Option<Value>, mut response: Value) -> Value {
+    if let Some(id) = id {
+        response["id"] = id;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::testing::table;
+
+    #[test]
+    fn answer_requests() {
+        let table = table(
+            &[("k", '中')],
+            &[("中", "khk"), ("国", "lgyi"), ("中国", "khlg")],
+        );
+        let options = LoadOptions::default();
+        let server = Server::new(&table, &options);
+
+        assert_eq!(
+            server.answer(r#"{"op":"lookup","code":"khlg","id":1}"#),
+            json!({ "id": 1, "result": { "code": "khlg", "candidates": ["中国"] } })
+        );
+        assert_eq!(
+            server.answer(r#"[{"op":"encode_phrase","text":"中国"},{"op":"reverse","text":"中"}]"#),
+            json!([
+                { "result": { "text": "中国", "code": "khlg" } },
+                { "result": { "text": "中", "codes": [
+                    { "code": "k", "kind": "simplified", "rank": 0, "practical": true },
+                    { "code": "khk", "kind": "full", "rank": 0, "practical": false },
+                ] } },
+            ])
+        );
+        assert_eq!(
+            server.answer(r#"{"op":"encode_phrase","text":"中华"}"#)["error"]["kind"],
+            "character missing from full table"
+        );
+        assert_eq!(
+            server.answer(r#"{"op":"lookup","code":"kz"}"#)["error"]["kind"],
+            "invalid letters"
+        );
+        assert_eq!(server.answer("{")["error"]["kind"], "invalid request");
+    }
+}
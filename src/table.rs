@@ -1,15 +1,105 @@
-use super::ParseError;
-use crate::WubiEntry;
+use crate::{
+    ParseError, WubiEntry,
+    block::Block,
+    filter::{Filter, Filters},
+    rule::{PhraseDerivation, PhraseRules},
+};
 use arrayvec::ArrayVec;
-use std::{collections::BTreeMap, fmt};
+use itertools::{EitherOrBoth, Itertools as _};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::{Bound, Range},
+    str::FromStr,
+    sync::LazyLock,
+};
 
 const INDEX_UPPER_BOUND: usize = 26_u32.strict_pow(4) as usize;
 
-#[derive(PartialEq, Debug, Copy, Clone, Eq, PartialOrd, Ord)]
+/// A Wubi code of one to four letters in `a..=y`.
+///
+/// Codes are stored as a dense index so that sorting by index is the same as
+/// sorting the codes lexicographically.
+#[derive(PartialEq, Debug, Copy, Clone, Eq, PartialOrd, Ord, Hash)]
 pub struct WubiCode {
     index: u32,
 }
 
+impl WubiCode {
+    /// Letters a code can be made of.
+    pub const ALPHABET: &str = "abcdefghijklmnopqrstuvwxy";
+    /// Maximum number of letters (keys) in a code.
+    pub const MAX_KEYS: usize = 4;
+
+    /// Dense index of the code, below `26^4`.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub(crate) fn from_index(index: u32) -> Self {
+        debug_assert!((index as usize) < INDEX_UPPER_BOUND);
+        Self { index }
+    }
+
+    /// Value of the `position`-th letter (from 0): 1 for `a` up to 25 for `y`,
+    /// or 0 past the end of the code.
+    pub(crate) fn digit(&self, position: usize) -> u32 {
+        match position {
+            0..4 => self.index / 26_u32.pow(3 - position as u32) % 26,
+            _ => 0,
+        }
+    }
+
+    /// Number of letters (keys) in the code.
+    pub fn key_count(&self) -> usize {
+        (0..4)
+            .rev()
+            .take_while(|&i| !(self.index / 26_u32.pow(i)).is_multiple_of(26))
+            .count()
+    }
+}
+
+/// Which of several full codes of a character is used to derive phrase codes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CodeChoice {
+    /// The code listed first in the source file.
+    #[default]
+    First,
+    /// The longest code, or the first of the longest ones.
+    Longest,
+    /// The shortest code, or the first of the shortest ones.
+    Shortest,
+}
+
+impl CodeChoice {
+    pub const ALL: [CodeChoice; 3] = [CodeChoice::First, CodeChoice::Longest, CodeChoice::Shortest];
+
+    /// Name used on the command line.
+    pub fn key(self) -> &'static str {
+        match self {
+            CodeChoice::First => "first",
+            CodeChoice::Longest => "longest",
+            CodeChoice::Shortest => "shortest",
+        }
+    }
+}
+
+impl FromStr for CodeChoice {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CodeChoice::ALL
+            .into_iter()
+            .find(|choice| choice.key() == s)
+            .ok_or_else(|| {
+                let keys = CodeChoice::ALL.into_iter().map(CodeChoice::key).join(", ");
+                ParseError::UnknownName(s.to_string(), keys)
+            })
+    }
+}
+
 impl TryFrom<&[u8]> for WubiCode {
     type Error = ParseError;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
@@ -94,9 +184,19 @@ impl fmt::Display for WubiCode {
     }
 }
 
+/// Full codes of characters and phrases, indexed in both directions.
+///
+/// A phrase may have several codes (容错码); they are kept in insertion order
+/// without duplicates. The phrases of a code are ordered by decreasing weight,
+/// in insertion order among equal weights; a phrase without a weight counts
+/// as weighing 0. The insertion order itself is kept too, see
+/// [`Self::insertion_index`].
 pub struct FullCodeTable {
     code_to_phrases: Vec<Vec<String>>,
-    phrase_to_code: BTreeMap<String, WubiCode>,
+    phrase_to_code: BTreeMap<String, Vec<WubiCode>>,
+    weights: HashMap<String, u32>,
+    insertions: HashMap<String, Vec<(WubiCode, usize)>>,
+    insertion_count: usize,
 }
 
 impl FullCodeTable {
@@ -109,35 +209,121 @@ impl FullCodeTable {
         Self {
             code_to_phrases,
             phrase_to_code,
+            weights: HashMap::new(),
+            insertions: HashMap::new(),
+            insertion_count: 0,
         }
     }
 
+    pub fn phrases(&self, code: &WubiCode) -> &[String] {
+        &self.code_to_phrases[code.index as usize]
+    }
+
     pub fn phrases_mut(&mut self, code: &WubiCode) -> &mut Vec<String> {
         &mut self.code_to_phrases[code.index as usize]
     }
 
-    pub fn code_mut(&mut self, phrase: &String) -> Option<&mut WubiCode> {
+    pub fn codes_mut(&mut self, phrase: &str) -> Option<&mut Vec<WubiCode>> {
         self.phrase_to_code.get_mut(phrase)
     }
 
-    pub fn code(&self, phrase: &String) -> Option<&WubiCode> {
-        self.phrase_to_code.get(phrase)
+    /// All codes of `phrase`, in insertion order.
+    pub fn codes(&self, phrase: &str) -> &[WubiCode] {
+        self.phrase_to_code.get(phrase).map_or(&[], Vec::as_slice)
     }
 
-    pub fn insert(&mut self, entry: WubiEntry) {
-        if let Some(code_mut) = self.code_mut(&entry.phrase) {
-            // code_mut.push(entry.wubi_code);
-            println!("{}", entry.phrase);
-            println!("{}", code_mut);
-            panic!();
-        } else {
-            self.phrase_to_code
-                .insert(entry.phrase.clone(), entry.wubi_code);
+    /// Every phrase with its codes, in phrase order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[WubiCode])> {
+        self.phrase_to_code
+            .iter()
+            .map(|(phrase, codes)| (phrase.as_str(), codes.as_slice()))
+    }
+
+    /// The first code of `phrase`.
+    pub fn code(&self, phrase: &str) -> Option<&WubiCode> {
+        self.codes(phrase).first()
+    }
+
+    /// The code of `phrase` selected by `choice`.
+    pub fn canonical_code(&self, phrase: &str, choice: CodeChoice) -> Option<WubiCode> {
+        let codes = self.codes(phrase).iter().copied();
+        match choice {
+            CodeChoice::First => self.code(phrase).copied(),
+            CodeChoice::Longest => codes.rev().max_by_key(WubiCode::key_count),
+            CodeChoice::Shortest => codes.min_by_key(WubiCode::key_count),
+        }
+    }
+
+    /// Weight of `phrase`, if it was given one.
+    pub fn weight(&self, phrase: &str) -> Option<u32> {
+        self.weights.get(phrase).copied()
+    }
+
+    /// Position of the entry of `phrase` under `code` among all entries, in
+    /// insertion order whatever their weights.
+    pub fn insertion_index(&self, phrase: &str, code: &WubiCode) -> Option<usize> {
+        insertion_index(&self.insertions, phrase, code)
+    }
+
+    /// Sets the weight of `phrase`, moving it among the phrases of each of its
+    /// codes so that they stay ordered by decreasing weight, then by
+    /// insertion order.
+    pub fn set_weight(&mut self, phrase: &str, weight: u32) {
+        self.weights.insert(phrase.to_string(), weight);
+        let (weights, insertions) = (&self.weights, &self.insertions);
+        for code in self.phrase_to_code.get(phrase).into_iter().flatten() {
+            self.code_to_phrases[code.index as usize].sort_by_key(|phrase| {
+                (
+                    Reverse(weights.get(phrase).copied().unwrap_or_default()),
+                    insertion_index(insertions, phrase, code),
+                )
+            });
+        }
+    }
+
+    /// Adds `entry` after the phrases of its code weighing at least as much.
+    ///
+    /// The weight of an entry, if any, replaces the one `phrase` had, as with
+    /// [`Self::set_weight`].
+    pub fn insert(&mut self, entry: WubiEntry) -> Result<(), ParseError> {
+        let codes = self.phrase_to_code.entry(entry.phrase.clone()).or_default();
+        if codes.contains(&entry.wubi_code) {
+            return Err(ParseError::DuplicatePhrase(entry.phrase));
+        }
+        if let Some(weight) = entry.weight {
+            self.set_weight(&entry.phrase, weight);
         }
-        self.phrases_mut(&entry.wubi_code).push(entry.phrase);
+        self.phrase_to_code
+            .get_mut(&entry.phrase)
+            .expect("Added above")
+            .push(entry.wubi_code);
+        self.insertions
+            .entry(entry.phrase.clone())
+            .or_default()
+            .push((entry.wubi_code, self.insertion_count));
+        self.insertion_count += 1;
+        let weight = self.weight(&entry.phrase).unwrap_or_default();
+        let phrases = &self.code_to_phrases[entry.wubi_code.index as usize];
+        let position =
+            phrases.partition_point(|phrase| self.weight(phrase).unwrap_or_default() >= weight);
+        self.phrases_mut(&entry.wubi_code)
+            .insert(position, entry.phrase);
+        Ok(())
     }
 }
 
+fn insertion_index(
+    insertions: &HashMap<String, Vec<(WubiCode, usize)>>,
+    phrase: &str,
+    code: &WubiCode,
+) -> Option<usize> {
+    insertions
+        .get(phrase)?
+        .iter()
+        .find(|(inserted_code, _)| inserted_code == code)
+        .map(|&(_, index)| index)
+}
+
 impl Default for FullCodeTable {
     fn default() -> Self {
         Self::new()
@@ -148,53 +334,58 @@ const CHAR_MIN: char = '\u{2eb3}';
 const CHAR_MAX: char = '\u{9fff}';
 const CHAR_COUNT: usize = ((CHAR_MAX as u16) - CHAR_MIN as u16 + 1) as usize;
 
+type SimplifiedCodes = ArrayVec<WubiCode, 3>;
+
+/// Simplified (1- to 3-letter) codes of single characters.
+///
+/// Characters in `CHAR_MIN..=CHAR_MAX` (CJK radicals up to the URO block) are
+/// stored densely; any other character goes into a sparse map.
 pub struct SimplifiedCodeTable {
-    code_to_char: Box<ArrayVec<Option<char>, INDEX_UPPER_BOUND>>,
-    char_to_code: Box<ArrayVec<ArrayVec<WubiCode, 3>, CHAR_COUNT>>,
+    code_to_char: Vec<Option<char>>,
+    char_to_code: Vec<SimplifiedCodes>,
+    sparse_char_to_code: BTreeMap<char, SimplifiedCodes>,
 }
 
 impl SimplifiedCodeTable {
     pub fn new() -> Self {
-        let mut code_to_char = Box::new(ArrayVec::new());
-        let mut char_to_code = Box::new(ArrayVec::new());
-        code_to_char.extend(std::iter::repeat_n(None, INDEX_UPPER_BOUND));
-        char_to_code.extend(std::iter::repeat_n(ArrayVec::new(), CHAR_COUNT));
+        let code_to_char = vec![None; INDEX_UPPER_BOUND];
+        let char_to_code = vec![ArrayVec::new(); CHAR_COUNT];
         Self {
             code_to_char,
             char_to_code,
+            sparse_char_to_code: BTreeMap::new(),
         }
     }
 
     pub fn insert(&mut self, code: &WubiCode, ch: char) -> Result<(), ParseError> {
-        let char_ref = self.char_of_code_mut(code);
-        if char_ref.is_some() {
-            return Err(ParseError::Invalid);
+        if let Some(existing) = *self.char_of_code(code) {
+            return Err(ParseError::CodeTaken(existing));
         }
-        *char_ref = Some(ch);
-        if let Some(code_ref) = self.code_of_char_mut(ch) {
-            if code_ref.len() == 3 {
-                println!("{ch} {:?} {:?}", code_ref, code);
-            }
-            code_ref.push(*code);
-            Ok(())
-        } else {
-            eprintln!("{:x}", ch as usize);
-            Err(ParseError::NotValidChar)
+        let code_ref = self.code_of_char_mut(ch);
+        if code_ref.is_full() {
+            return Err(ParseError::TooManyCodes(ch));
         }
+        code_ref.push(*code);
+        *self.char_of_code_mut(code) = Some(ch);
+        Ok(())
     }
 
-    pub fn code_of_char(&self, ch: char) -> Option<&ArrayVec<WubiCode, 3>> {
-        if !(CHAR_MIN..=CHAR_MAX).contains(&ch) {
-            return None;
-        }
-        Some(&self.char_to_code[ch as usize - CHAR_MIN as usize])
+    /// Simplified codes of `ch`, shortest first, or `None` if it has none.
+    pub fn code_of_char(&self, ch: char) -> Option<&SimplifiedCodes> {
+        let codes = if (CHAR_MIN..=CHAR_MAX).contains(&ch) {
+            &self.char_to_code[ch as usize - CHAR_MIN as usize]
+        } else {
+            self.sparse_char_to_code.get(&ch)?
+        };
+        if codes.is_empty() { None } else { Some(codes) }
     }
 
-    pub fn code_of_char_mut(&mut self, ch: char) -> Option<&mut ArrayVec<WubiCode, 3>> {
-        if !(CHAR_MIN..=CHAR_MAX).contains(&ch) {
-            return None;
+    pub fn code_of_char_mut(&mut self, ch: char) -> &mut SimplifiedCodes {
+        if (CHAR_MIN..=CHAR_MAX).contains(&ch) {
+            &mut self.char_to_code[ch as usize - CHAR_MIN as usize]
+        } else {
+            self.sparse_char_to_code.entry(ch).or_default()
         }
-        Some(&mut self.char_to_code[ch as usize - CHAR_MIN as usize])
     }
 
     pub fn char_of_code(&self, code: &WubiCode) -> &Option<char> {
@@ -204,6 +395,23 @@ impl SimplifiedCodeTable {
     pub fn char_of_code_mut(&mut self, code: &WubiCode) -> &mut Option<char> {
         &mut self.code_to_char[code.index as usize]
     }
+
+    /// Characters with simplified codes, in codepoint order.
+    fn chars(&self) -> impl Iterator<Item = (char, &SimplifiedCodes)> {
+        let dense = self.char_to_code.iter().enumerate().map(|(index, codes)| {
+            let ch = char::from_u32(CHAR_MIN as u32 + index as u32).unwrap();
+            (ch, codes)
+        });
+        let sparse = |range: (Bound<char>, Bound<char>)| {
+            self.sparse_char_to_code
+                .range(range)
+                .map(|(ch, codes)| (*ch, codes))
+        };
+        sparse((Bound::Unbounded, Bound::Excluded(CHAR_MIN)))
+            .chain(dense)
+            .chain(sparse((Bound::Excluded(CHAR_MAX), Bound::Unbounded)))
+            .filter(|(_, codes)| !codes.is_empty())
+    }
 }
 
 impl Default for SimplifiedCodeTable {
@@ -212,14 +420,111 @@ impl Default for SimplifiedCodeTable {
     }
 }
 
+/// A candidate shown for a code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Candidate<'a> {
+    /// The character with this simplified code.
+    Simplified(char),
+    /// A phrase or character from the full table.
+    Full(&'a str),
+}
+
+impl fmt::Display for Candidate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Candidate::Simplified(ch) => write!(f, "{ch}"),
+            Candidate::Full(phrase) => f.write_str(phrase),
+        }
+    }
+}
+
+/// Where a code of a phrase comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeKind {
+    Simplified,
+    Full,
+}
+
+/// One way to type a phrase, as returned by [`Table::codes_of`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhraseCode {
+    pub code: WubiCode,
+    pub kind: CodeKind,
+    /// Position of the phrase among the candidates of the code, or `None` if
+    /// the table hides it there.
+    pub rank: Option<usize>,
+    /// Whether this is the shortest practical code: the one needing the
+    /// fewest keystrokes, counting a selection key when the phrase is not
+    /// the first candidate.
+    pub practical: bool,
+}
+
+/// Candidates of a code in display order.
+pub type Candidates<'a> = Vec<Candidate<'a>>;
+
+/// Simplified and full code tables combined for output.
 pub struct Table {
     simplified: SimplifiedCodeTable,
     full: FullCodeTable,
+    filters: Filters,
 }
 
 impl Table {
+    /// Combines the tables, with every [`Filter`] enabled.
     pub fn new(simplified: SimplifiedCodeTable, full: FullCodeTable) -> Self {
-        Self { simplified, full }
+        Self {
+            simplified,
+            full,
+            filters: Filters::default(),
+        }
+    }
+
+    pub fn with_filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn filters(&self) -> &Filters {
+        &self.filters
+    }
+
+    pub fn simplified(&self) -> &SimplifiedCodeTable {
+        &self.simplified
+    }
+
+    pub fn full(&self) -> &FullCodeTable {
+        &self.full
+    }
+
+    /// Weights of `candidates` for formats that order candidates by weight:
+    /// the weight of each one in the full table, raised where needed so that
+    /// they strictly decrease along the candidates, down to at least 1.
+    pub fn candidate_weights(&self, candidates: &[Candidate<'_>]) -> Vec<u32> {
+        let mut weights = vec![0; candidates.len()];
+        let mut next = 0;
+        for (weight, candidate) in weights.iter_mut().zip(candidates).rev() {
+            let stored = match candidate {
+                Candidate::Simplified(ch) => self.full.weight(&ch.to_string()),
+                Candidate::Full(phrase) => self.full.weight(phrase),
+            };
+            *weight = stored.unwrap_or_default().max(next + 1);
+            next = *weight;
+        }
+        weights
+    }
+
+    /// Number of single characters in the full table per Unicode block.
+    pub fn block_counts(&self) -> BTreeMap<Block, usize> {
+        let mut counts = BTreeMap::new();
+        for phrase in self.full.phrase_to_code.keys() {
+            let mut chars = phrase.chars();
+            if let Some(ch) = chars.next()
+                && chars.next().is_none()
+            {
+                *counts.entry(Block::of(ch)).or_default() += 1;
+            }
+        }
+        counts
     }
 
     pub fn simplified_table(&self) -> impl Iterator<Item = (WubiCode, char)> {
@@ -245,18 +550,44 @@ impl Table {
             })
     }
 
+    /// Whether `phrase` was loaded under `code` before the character of that
+    /// simplified code, as hidden by [`Filter::HideBeforeSimplified`]: only
+    /// the load order counts, not the weights. If the character is not a
+    /// full-table entry of the code, every phrase of the code counts.
+    pub fn loaded_before_simplified(&self, code: &WubiCode, phrase: &str) -> bool {
+        let Some(simplified_ch) = *self.simplified.char_of_code(code) else {
+            return false;
+        };
+        let mut buf = [0; 4];
+        let simplified_ch = &*simplified_ch.encode_utf8(&mut buf);
+        match self.full.insertion_index(simplified_ch, code) {
+            Some(simplified) => self
+                .full
+                .insertion_index(phrase, code)
+                .is_some_and(|index| index < simplified),
+            None => true,
+        }
+    }
+
+    /// Full-table phrases of `code` that are shown: with
+    /// [`Filter::HideBeforeSimplified`], not those
+    /// [loaded before the simplified character](Self::loaded_before_simplified).
+    pub(crate) fn filtered_phrases(&self, code: &WubiCode) -> Vec<&String> {
+        let phrases = self.full.phrases(code).iter();
+        if self.filters.contains(Filter::HideBeforeSimplified) {
+            phrases
+                .filter(|phrase| !self.loaded_before_simplified(code, phrase))
+                .collect()
+        } else {
+            phrases.collect()
+        }
+    }
+
     pub fn filtered_full_table(
         &self,
     ) -> impl Iterator<Item = (WubiCode, impl Iterator<Item = &String>)> {
-        self.reverse_full_table().filter_map(|(code, phrases)| {
-            let phrases: Vec<_> = if let Some(simplified_ch) = self.simplified.char_of_code(&code) {
-                let phrases = phrases
-                    .iter()
-                    .skip_while(|phrase| **phrase != simplified_ch.to_string());
-                phrases.collect()
-            } else {
-                phrases.iter().collect()
-            };
+        self.reverse_full_table().filter_map(|(code, _)| {
+            let phrases = self.filtered_phrases(&code);
             if phrases.is_empty() {
                 None
             } else {
@@ -269,79 +600,243 @@ impl Table {
         &self,
     ) -> impl Iterator<Item = (char, impl Iterator<Item = WubiCode>)> {
         self.simplified
-            .char_to_code
-            .iter()
-            .enumerate()
-            .filter_map(|(index, codes)| {
-                if codes.is_empty() {
-                    return None;
-                }
-                let ch = char::from_u32(CHAR_MIN as u32 + index as u32).unwrap();
-                let codes = codes.iter().map(|code| {
-                    let index = code.index;
-                    WubiCode { index }
-                });
-                Some((ch, codes))
-            })
+            .chars()
+            .map(|(ch, codes)| (ch, codes.iter().copied()))
     }
 
-    fn full_table(&self) -> impl Iterator<Item = (&String, &WubiCode)> {
+    fn full_table(&self) -> impl Iterator<Item = (&String, &Vec<WubiCode>)> {
         self.full.phrase_to_code.iter()
     }
 
-    pub fn reverse_filtered_full_table(&self) -> impl Iterator<Item = (&String, &WubiCode)> {
-        self.full_table().filter_map(|(phrase, code)| {
+    /// Full codes of each phrase, without those also being simplified codes
+    /// of a character if [`Filter::HideDuplicateCodes`] is enabled.
+    pub fn reverse_filtered_full_table(
+        &self,
+    ) -> impl Iterator<Item = (&String, impl Iterator<Item = &WubiCode>)> {
+        self.full_table().filter_map(|(phrase, codes)| {
             let mut chars = phrase.chars();
-            if let Some(ch) = chars.next()
+            let simplified_codes = if self.filters.contains(Filter::HideDuplicateCodes)
+                && let Some(ch) = chars.next()
                 && chars.next().is_none()
                 && let Some(simplified_codes) = self.simplified.code_of_char(ch)
-                && let Some(longest_simplified_code) = simplified_codes.last()
-                && longest_simplified_code == code
             {
+                simplified_codes.as_slice()
+            } else {
+                &[]
+            };
+            let codes: Vec<_> = codes
+                .iter()
+                .filter(|code| !simplified_codes.contains(code))
+                .collect();
+            if codes.is_empty() {
                 None
             } else {
-                Some((phrase, code))
+                Some((phrase, codes.into_iter()))
             }
         })
     }
-}
 
-pub fn get_code_for_phrase(phrase: &str, char_code: impl Fn(char) -> WubiCode) -> WubiCode {
-    let mut chars = phrase.chars();
-    match chars.clone().count() {
-        0 | 1 => panic!(),
-        2 => {
-            let first = char_code(chars.next().unwrap());
-            let second = char_code(chars.next().unwrap());
-            let index = first.index / 26_u32.pow(2) * 26_u32.pow(2) + second.index / 26_u32.pow(2);
-            WubiCode { index }
+    /// Candidates of `code` in display order: the simplified character first,
+    /// then the remaining full-table phrases. Without
+    /// [`Filter::PromoteSimplified`], a simplified character that is also a
+    /// full-table entry of the code keeps the position of that entry.
+    pub fn lookup(&self, code: &WubiCode) -> Candidates<'_> {
+        let phrases = self.filtered_phrases(code);
+        match *self.simplified.char_of_code(code) {
+            Some(ch) => {
+                let mut buf = [0; 4];
+                let ch_str = &*ch.encode_utf8(&mut buf);
+                if !self.filters.contains(Filter::PromoteSimplified)
+                    && phrases.iter().any(|phrase| *phrase == ch_str)
+                {
+                    return phrases
+                        .into_iter()
+                        .map(|phrase| match phrase == ch_str {
+                            true => Candidate::Simplified(ch),
+                            false => Candidate::Full(phrase),
+                        })
+                        .collect();
+                }
+                let mut candidates = vec![Candidate::Simplified(ch)];
+                candidates.extend(
+                    phrases
+                        .into_iter()
+                        .filter(|phrase| *phrase != ch_str)
+                        .map(|phrase| Candidate::Full(phrase)),
+                );
+                candidates
+            }
+            None => phrases
+                .into_iter()
+                .map(|phrase| Candidate::Full(phrase))
+                .collect(),
+        }
+    }
+
+    /// Every code starting with `prefix` (including `prefix` itself) that has
+    /// candidates, in order, with its candidates as returned by [`Self::lookup`].
+    pub fn lookup_prefix(
+        &self,
+        prefix: &WubiCode,
+    ) -> impl Iterator<Item = (WubiCode, Candidates<'_>)> {
+        let span = 26_u32.pow((WubiCode::MAX_KEYS - prefix.key_count()) as u32);
+        self.lookup_range(prefix.index..prefix.index + span)
+    }
+
+    fn lookup_range(
+        &self,
+        indices: Range<u32>,
+    ) -> impl Iterator<Item = (WubiCode, Candidates<'_>)> {
+        indices.filter_map(|index| {
+            let code = WubiCode { index };
+            let candidates = self.lookup(&code);
+            if candidates.is_empty() {
+                None
+            } else {
+                Some((code, candidates))
+            }
+        })
+    }
+
+    /// Each code exactly once, in order, with its candidates as returned by
+    /// [`Self::lookup`].
+    pub fn candidates(&self) -> impl Iterator<Item = (WubiCode, Candidates<'_>)> {
+        self.lookup_range(0..INDEX_UPPER_BOUND as u32)
+    }
+
+    /// Every code of `phrase`, simplified and full, shortest first.
+    ///
+    /// At most one code is marked [`PhraseCode::practical`]; none is if the
+    /// phrase is hidden under all of its codes.
+    pub fn codes_of(&self, phrase: &str) -> Vec<PhraseCode> {
+        let mut chars = phrase.chars();
+        let simplified = match (chars.next(), chars.next()) {
+            (Some(ch), None) => self
+                .simplified
+                .code_of_char(ch)
+                .map(|codes| codes.as_slice()),
+            _ => None,
+        };
+        let mut codes: Vec<_> = simplified
+            .unwrap_or_default()
+            .iter()
+            .map(|code| (*code, CodeKind::Simplified))
+            .chain(
+                self.full
+                    .codes(phrase)
+                    .iter()
+                    .map(|code| (*code, CodeKind::Full)),
+            )
+            .unique_by(|(code, _)| *code)
+            .map(|(code, kind)| PhraseCode {
+                code,
+                kind,
+                rank: self
+                    .lookup(&code)
+                    .iter()
+                    .position(|candidate| candidate.to_string() == phrase),
+                practical: false,
+            })
+            .collect();
+        codes.sort_by_key(|code| code.code.key_count());
+        if let Some(practical) = codes
+            .iter_mut()
+            .filter_map(|code| Some((code.code.key_count() + usize::from(code.rank? > 0), code)))
+            .min_by_key(|(keystrokes, _)| *keystrokes)
+        {
+            practical.1.practical = true;
         }
-        3 => {
-            let first = char_code(chars.next().unwrap());
-            let second = char_code(chars.next().unwrap());
-            let third = char_code(chars.next().unwrap());
-            let index = first.index / 26_u32.pow(3) * 26_u32.pow(3)
-                + second.index / 26_u32.pow(3) * 26_u32.pow(2)
-                + third.index / 26_u32.pow(2);
-            WubiCode { index }
+        codes
+    }
+
+    /// Each phrase exactly once, in order, with its codes: simplified codes,
+    /// shortest first, then the remaining full codes, each code once.
+    pub fn reverse_entries(&self) -> impl Iterator<Item = (Cow<'_, str>, Vec<WubiCode>)> {
+        self.reverse_simplified_table()
+            .map(|(ch, codes)| (ch.to_string(), codes))
+            .merge_join_by(
+                self.reverse_filtered_full_table(),
+                |(ch, _), (phrase, _)| ch.as_str().cmp(phrase.as_str()),
+            )
+            .map(|entry| match entry {
+                EitherOrBoth::Left((ch, codes)) => (Cow::Owned(ch), codes.collect()),
+                EitherOrBoth::Right((phrase, codes)) => {
+                    (Cow::Borrowed(phrase.as_str()), codes.copied().collect())
+                }
+                EitherOrBoth::Both((ch, simplified_codes), (_, full_codes)) => {
+                    let codes = simplified_codes
+                        .chain(full_codes.copied())
+                        .unique()
+                        .collect();
+                    (Cow::Owned(ch), codes)
+                }
+            })
+    }
+}
+
+static RULES: LazyLock<PhraseRules> = LazyLock::new(PhraseRules::standard);
+
+/// Derives the code of a phrase of two or more characters from the full codes
+/// of its characters, as returned by `char_code`, using
+/// [`PhraseRules::standard`].
+///
+/// Fails with [`ParseError::MissingCharacter`] naming the first character
+/// `char_code` has no code for, or [`ParseError::NoRule`] for a phrase of
+/// fewer than two characters.
+pub fn get_code_for_phrase(
+    phrase: &str,
+    char_code: impl Fn(char) -> Option<WubiCode>,
+) -> Result<WubiCode, ParseError> {
+    derive_code_for_phrase(phrase, char_code).map(|derivation| derivation.code())
+}
+
+/// Like [`get_code_for_phrase`], explaining how the code was derived.
+pub fn derive_code_for_phrase(
+    phrase: &str,
+    char_code: impl Fn(char) -> Option<WubiCode>,
+) -> Result<PhraseDerivation<'static>, ParseError> {
+    if let Some(ch) = phrase.chars().find(|&ch| char_code(ch).is_none()) {
+        return Err(ParseError::MissingCharacter(ch));
+    }
+    RULES.derive(phrase, char_code)
+}
+
+/// Setup shared by the unit tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    pub(crate) fn code(s: &str) -> WubiCode {
+        WubiCode::try_from(s).unwrap()
+    }
+
+    /// Full table of `(phrase, code)` entries, inserted in order.
+    pub(crate) fn full(entries: &[(&str, &str)]) -> FullCodeTable {
+        let mut full = FullCodeTable::new();
+        for (phrase, wubi_code) in entries {
+            full.insert(WubiEntry::new(phrase.to_string(), code(wubi_code)))
+                .unwrap();
         }
-        4.. => {
-            let first = char_code(chars.next().unwrap());
-            let second = char_code(chars.next().unwrap());
-            let third = char_code(chars.next().unwrap());
-            let last = char_code(chars.last().unwrap());
-            let index = first.index / 26_u32.pow(3) * 26_u32.pow(3)
-                + second.index / 26_u32.pow(3) * 26_u32.pow(2)
-                + third.index / 26_u32.pow(3) * 26_u32.pow(1)
-                + last.index / 26_u32.pow(3);
-            WubiCode { index }
+        full
+    }
+
+    /// Table of `(code, character)` simplified entries and `(phrase, code)`
+    /// full entries.
+    pub(crate) fn table(simplified: &[(&str, char)], full: &[(&str, &str)]) -> Table {
+        let mut simplified_table = SimplifiedCodeTable::new();
+        for (wubi_code, ch) in simplified {
+            simplified_table.insert(&code(wubi_code), *ch).unwrap();
         }
+        Table::new(simplified_table, self::full(full))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{
+        testing::{code, table},
+        *,
+    };
 
     #[test]
     fn convert_wubi_code() {
@@ -355,4 +850,218 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn multiple_full_codes() {
+        assert_eq!(code("a").key_count(), 1);
+        assert_eq!(code("yyyy").key_count(), 4);
+
+        let mut full = FullCodeTable::new();
+        full.insert(WubiEntry::new("未".into(), code("fii")))
+            .unwrap();
+        full.insert(WubiEntry::new("未".into(), code("fiig")))
+            .unwrap();
+        assert_eq!(
+            full.insert(WubiEntry::new("未".into(), code("fii"))),
+            Err(ParseError::DuplicatePhrase("未".into()))
+        );
+        assert_eq!(full.codes("未"), [code("fii"), code("fiig")]);
+        assert_eq!(full.phrases(&code("fiig")), ["未"]);
+        assert_eq!(
+            full.canonical_code("未", CodeChoice::First),
+            Some(code("fii"))
+        );
+        assert_eq!(
+            full.canonical_code("未", CodeChoice::Longest),
+            Some(code("fiig"))
+        );
+        assert_eq!(
+            full.canonical_code("未", CodeChoice::Shortest),
+            Some(code("fii"))
+        );
+        assert_eq!("longest".parse(), Ok(CodeChoice::Longest));
+        assert_eq!(
+            "last".parse::<CodeChoice>(),
+            Err(ParseError::UnknownName(
+                "last".into(),
+                "first, longest, shortest".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn simplified_outside_dense_block() {
+        let mut simplified = SimplifiedCodeTable::new();
+        simplified.insert(&code("ggl"), '一').unwrap();
+        simplified.insert(&code("gg"), '𠀀').unwrap();
+        simplified.insert(&code("ll"), '〇').unwrap();
+        simplified.insert(&code("a"), 'A').unwrap();
+        assert_eq!(
+            simplified.insert(&code("a"), '工'),
+            Err(ParseError::CodeTaken('A'))
+        );
+        assert_eq!(
+            simplified.code_of_char('𠀀').unwrap().as_slice(),
+            [code("gg")]
+        );
+        assert_eq!(simplified.code_of_char('丁'), None);
+        assert_eq!(simplified.code_of_char('B'), None);
+
+        let table = Table::new(simplified, FullCodeTable::new());
+        let chars: Vec<_> = table.reverse_simplified_table().map(|(ch, _)| ch).collect();
+        assert_eq!(chars, ['A', '〇', '一', '𠀀']);
+    }
+
+    #[test]
+    fn merged_candidates() {
+        let table = table(
+            &[("a", '工'), ("aak", '戒')],
+            &[("工", "aaaa"), ("戒", "aak"), ("恭恭敬敬", "aaaa")],
+        );
+
+        let candidates: Vec<_> = table.candidates().collect();
+        assert_eq!(
+            candidates,
+            [
+                (code("a"), vec![Candidate::Simplified('工')]),
+                (
+                    code("aaaa"),
+                    vec![Candidate::Full("工"), Candidate::Full("恭恭敬敬")]
+                ),
+                (code("aak"), vec![Candidate::Simplified('戒')]),
+            ]
+        );
+
+        let reverse: Vec<_> = table.reverse_entries().collect();
+        assert_eq!(
+            reverse,
+            [
+                ("工".into(), vec![code("a"), code("aaaa")]),
+                ("恭恭敬敬".into(), vec![code("aaaa")]),
+                ("戒".into(), vec![code("aak")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup_prefix() {
+        let table = table(
+            &[],
+            &[("工", "aaaa"), ("式", "aad"), ("一", "ggll"), ("七", "ag")],
+        );
+        assert_eq!(table.lookup(&code("aad")), [Candidate::Full("式")]);
+        assert!(table.lookup(&code("aa")).is_empty());
+
+        let codes: Vec<_> = table.lookup_prefix(&code("aa")).map(|(c, _)| c).collect();
+        assert_eq!(codes, [code("aaaa"), code("aad")]);
+        let codes: Vec<_> = table.lookup_prefix(&code("a")).map(|(c, _)| c).collect();
+        assert_eq!(codes, [code("aaaa"), code("aad"), code("ag")]);
+        assert_eq!(table.lookup_prefix(&code("ggll")).count(), 1);
+    }
+
+    #[test]
+    fn codes_of_phrase() {
+        let table = table(
+            &[("x", '经'), ("xca", '经'), ("xc", '纪')],
+            &[("经", "xcag"), ("经济", "xciy"), ("给", "xwgk")],
+        );
+
+        let codes = table.codes_of("经");
+        let summary: Vec<_> = codes
+            .iter()
+            .map(|c| (c.code, c.kind, c.practical))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (code("x"), CodeKind::Simplified, true),
+                (code("xca"), CodeKind::Simplified, false),
+                (code("xcag"), CodeKind::Full, false),
+            ]
+        );
+        assert_eq!(table.codes_of("经济")[0].rank, Some(0));
+        assert!(table.codes_of("纪律").is_empty());
+    }
+
+    #[test]
+    fn weighted_phrases() {
+        let mut full = FullCodeTable::new();
+        for (phrase, weight) in [
+            ("跨国", None),
+            ("中国", Some(10)),
+            ("跑车", Some(3)),
+            ("贵国", Some(3)),
+        ] {
+            full.insert(WubiEntry::new(phrase.into(), code("khlg")).with_weight(weight))
+                .unwrap();
+        }
+        assert_eq!(
+            full.phrases(&code("khlg")),
+            ["中国", "跑车", "贵国", "跨国"]
+        );
+        assert_eq!(full.weight("中国"), Some(10));
+        assert_eq!(full.weight("跨国"), None);
+
+        full.insert(WubiEntry::new("贵国".into(), code("khlh")).with_weight(Some(20)))
+            .unwrap();
+        assert_eq!(
+            full.phrases(&code("khlg")),
+            ["贵国", "中国", "跑车", "跨国"]
+        );
+
+        let table = Table::new(SimplifiedCodeTable::new(), full);
+        let candidates = table.lookup(&code("khlg"));
+        assert_eq!(table.candidate_weights(&candidates), [20, 10, 3, 1]);
+
+        let mut full = table.full;
+        full.set_weight("贵国", 3);
+        assert_eq!(
+            full.phrases(&code("khlg")),
+            ["中国", "跑车", "贵国", "跨国"]
+        );
+        full.set_weight("贵国", 0);
+        assert_eq!(
+            full.phrases(&code("khlg")),
+            ["中国", "跑车", "跨国", "贵国"]
+        );
+    }
+
+    #[test]
+    fn weights_do_not_hide_phrases() {
+        let mut table = table(
+            &[("agn", '七')],
+            &[("七", "agn"), ("匚", "agn"), ("匸", "agn")],
+        );
+        assert_eq!(
+            table.lookup(&code("agn")),
+            [
+                Candidate::Simplified('七'),
+                Candidate::Full("匚"),
+                Candidate::Full("匸")
+            ]
+        );
+
+        table.full.set_weight("匚", 100);
+        assert_eq!(table.full.phrases(&code("agn")), ["匚", "七", "匸"]);
+        assert_eq!(
+            table.lookup(&code("agn")),
+            [
+                Candidate::Simplified('七'),
+                Candidate::Full("匚"),
+                Candidate::Full("匸")
+            ]
+        );
+        assert_eq!(table.codes_of("匚")[0].rank, Some(1));
+        assert!(crate::FilterReport::new(&table).hidden_phrases.is_empty());
+
+        let table = table.with_filters(Filters::only([Filter::HideBeforeSimplified]));
+        assert_eq!(
+            table.lookup(&code("agn")),
+            [
+                Candidate::Full("匚"),
+                Candidate::Simplified('七'),
+                Candidate::Full("匸")
+            ]
+        );
+    }
 }